@@ -0,0 +1,427 @@
+use std::collections::HashMap;
+use std::hash::Hash;
+use std::marker::PhantomData;
+
+use crate::huffman::{EncodedData, HuffmanError, RawSymbol};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum NodeKind<S> {
+    // Not yet transmitted: the escape that stands in for every unseen symbol.
+    Nyt,
+    Leaf(S),
+    Internal { left: usize, right: usize },
+}
+
+#[derive(Debug, Clone, Copy)]
+struct Node<S> {
+    weight: u64,
+    parent: usize,
+    kind: NodeKind<S>,
+}
+
+// How the tree is rearranged when a symbol's weight goes up.
+pub(crate) trait UpdateRule {
+    fn update<S: Hash + Eq + Copy>(tree: &mut AdaptiveTree<S>, leaf: usize);
+}
+
+// Faller, Gallager and Knuth: before incrementing a node, swap it with the
+// highest numbered node of the same weight.
+pub(crate) struct Fgk;
+
+// Vitter's algorithm Λ: like FGK, but leaves are kept numbered below internal
+// nodes of the same weight, which keeps the tree as shallow as possible. It
+// never uses more than one bit per symbol more than a static Huffman code.
+pub(crate) struct Vitter;
+
+// Nodes are stored in order of decreasing implicit number, so the root is at
+// index 0, weights never increase along the vector and the two children of a
+// node are always next to each other. Swapping two nodes only swaps their
+// weights and contents: positions keep their parents, and the children and
+// symbol index are patched up to match.
+#[derive(Debug)]
+pub(crate) struct AdaptiveTree<S> {
+    nodes: Vec<Node<S>>,
+    leaves: HashMap<S, usize>,
+    nyt: usize,
+}
+
+const NO_PARENT: usize = usize::MAX;
+
+impl<S: Hash + Eq + Copy> AdaptiveTree<S> {
+    fn new() -> Self {
+        Self {
+            nodes: vec![Node {
+                weight: 0,
+                parent: NO_PARENT,
+                kind: NodeKind::Nyt,
+            }],
+            leaves: HashMap::new(),
+            nyt: 0,
+        }
+    }
+
+    fn is_leaf(&self, index: usize) -> bool {
+        !matches!(self.nodes[index].kind, NodeKind::Internal { .. })
+    }
+
+    fn parent(&self, index: usize) -> Option<usize> {
+        Some(self.nodes[index].parent).filter(|&parent| parent != NO_PARENT)
+    }
+
+    fn attach(&mut self, index: usize) {
+        match self.nodes[index].kind {
+            NodeKind::Nyt => self.nyt = index,
+            NodeKind::Leaf(symbol) => {
+                self.leaves.insert(symbol, index);
+            }
+            NodeKind::Internal { left, right } => {
+                self.nodes[left].parent = index;
+                self.nodes[right].parent = index;
+            }
+        }
+    }
+
+    fn swap(&mut self, a: usize, b: usize) {
+        if a == b {
+            return;
+        }
+        let (weight, kind) = (self.nodes[a].weight, self.nodes[a].kind);
+        self.nodes[a].weight = self.nodes[b].weight;
+        self.nodes[a].kind = self.nodes[b].kind;
+        self.nodes[b].weight = weight;
+        self.nodes[b].kind = kind;
+        self.attach(a);
+        self.attach(b);
+    }
+
+    // Turns the NYT node into the parent of a new NYT node and a leaf for
+    // `symbol`, both of weight zero, and returns the leaf.
+    fn split_nyt(&mut self, symbol: S) -> usize {
+        let parent = self.nyt;
+        let leaf = self.nodes.len();
+        let nyt = leaf + 1;
+        for kind in [NodeKind::Leaf(symbol), NodeKind::Nyt] {
+            self.nodes.push(Node {
+                weight: 0,
+                parent,
+                kind,
+            });
+        }
+        self.nodes[parent].kind = NodeKind::Internal {
+            left: nyt,
+            right: leaf,
+        };
+        self.leaves.insert(symbol, leaf);
+        self.nyt = nyt;
+        leaf
+    }
+
+    // The path from the root, as `true` for every right turn.
+    fn code(&self, mut index: usize) -> Vec<bool> {
+        let mut code = Vec::new();
+        while let Some(parent) = self.parent(index) {
+            code.push(matches!(
+                self.nodes[parent].kind,
+                NodeKind::Internal { right, .. } if right == index
+            ));
+            index = parent;
+        }
+        code.reverse();
+        code
+    }
+}
+
+impl UpdateRule for Fgk {
+    fn update<S: Hash + Eq + Copy>(tree: &mut AdaptiveTree<S>, leaf: usize) {
+        let mut index = leaf;
+        loop {
+            let weight = tree.nodes[index].weight;
+            let leader = tree.nodes[..index].partition_point(|node| node.weight > weight);
+            if Some(leader) != tree.parent(index) {
+                tree.swap(index, leader);
+                index = leader;
+            }
+            tree.nodes[index].weight += 1;
+            match tree.parent(index) {
+                Some(parent) => index = parent,
+                None => return,
+            }
+        }
+    }
+}
+
+impl Vitter {
+    // Moves the node ahead of the nodes of its own block and of the block that
+    // follows it, which is the internal nodes of the same weight for a leaf
+    // and the leaves of one more weight for an internal node, then increments
+    // it. Returns the node to continue from.
+    fn slide_and_increment<S: Hash + Eq + Copy>(
+        tree: &mut AdaptiveTree<S>,
+        mut index: usize,
+    ) -> Option<usize> {
+        let weight = tree.nodes[index].weight;
+        let is_leaf = tree.is_leaf(index);
+        let former_parent = tree.parent(index);
+        while index > 0 {
+            let ahead = &tree.nodes[index - 1];
+            let ahead_is_leaf = tree.is_leaf(index - 1);
+            let slides_past = if is_leaf {
+                ahead.weight == weight
+            } else {
+                (ahead.weight == weight && !ahead_is_leaf)
+                    || (ahead.weight == weight + 1 && ahead_is_leaf)
+            };
+            if !slides_past {
+                break;
+            }
+            tree.swap(index, index - 1);
+            index -= 1;
+        }
+        tree.nodes[index].weight += 1;
+        if is_leaf {
+            tree.parent(index)
+        } else {
+            former_parent
+        }
+    }
+}
+
+impl UpdateRule for Vitter {
+    fn update<S: Hash + Eq + Copy>(tree: &mut AdaptiveTree<S>, leaf: usize) {
+        let mut index;
+        // A leaf that is the sibling of the NYT node has the same weight as its
+        // parent, so it is incremented last to keep it from sliding past it.
+        let mut leaf_to_increment = None;
+        if tree.nodes[leaf].weight == 0 {
+            leaf_to_increment = Some(leaf);
+            index = tree.parent(leaf).unwrap();
+        } else {
+            let weight = tree.nodes[leaf].weight;
+            let mut leader = leaf;
+            while leader > 0 && tree.nodes[leader - 1].weight == weight && tree.is_leaf(leader - 1)
+            {
+                leader -= 1;
+            }
+            tree.swap(leaf, leader);
+            index = leader;
+            if tree.parent(index) == tree.parent(tree.nyt) {
+                leaf_to_increment = Some(index);
+                index = tree.parent(index).unwrap();
+            }
+        }
+        let mut next = Some(index);
+        while let Some(index) = next {
+            next = Self::slide_and_increment(tree, index);
+        }
+        if let Some(leaf) = leaf_to_increment {
+            Self::slide_and_increment(tree, leaf);
+        }
+    }
+}
+
+// Sender and receiver both start from a tree holding only the NYT node and
+// apply the same update after every symbol, so no code table is sent. A symbol
+// seen for the first time is sent as the code of the NYT node followed by its
+// raw bits.
+pub(crate) struct AdaptiveHuffman<S = char, U = Vitter> {
+    tree: AdaptiveTree<S>,
+    rule: PhantomData<U>,
+}
+
+impl<S: RawSymbol, U: UpdateRule> AdaptiveHuffman<S, U> {
+    pub(crate) fn new() -> Self {
+        Self {
+            tree: AdaptiveTree::new(),
+            rule: PhantomData,
+        }
+    }
+
+    pub(crate) fn encode_symbol(&mut self, symbol: S, encoded_data: &mut EncodedData) {
+        let leaf = match self.tree.leaves.get(&symbol) {
+            Some(&leaf) => {
+                for bit in self.tree.code(leaf) {
+                    encoded_data.push_bit(bit);
+                }
+                leaf
+            }
+            None => {
+                for bit in self.tree.code(self.tree.nyt) {
+                    encoded_data.push_bit(bit);
+                }
+                let bits = symbol.to_bits();
+                for shift in (0..S::BITS).rev() {
+                    encoded_data.push_bit(bits & (1 << shift) != 0);
+                }
+                self.tree.split_nyt(symbol)
+            }
+        };
+        U::update(&mut self.tree, leaf);
+    }
+
+    pub(crate) fn decode_symbol<I>(&mut self, bits: &mut I) -> Result<S, HuffmanError>
+    where
+        I: Iterator<Item = bool>,
+    {
+        let mut index = 0;
+        let leaf = loop {
+            match self.tree.nodes[index].kind {
+                NodeKind::Internal { left, right } => {
+                    let bit = bits.next().ok_or(HuffmanError::TruncatedStream)?;
+                    index = if bit { right } else { left };
+                }
+                NodeKind::Leaf(_) => break index,
+                NodeKind::Nyt => {
+                    let mut value = 0;
+                    for _ in 0..S::BITS {
+                        let bit = bits.next().ok_or(HuffmanError::TruncatedStream)?;
+                        value = (value << 1) | bit as u32;
+                    }
+                    let symbol = S::from_bits(value).ok_or(HuffmanError::InvalidEscape(value))?;
+                    break self.tree.split_nyt(symbol);
+                }
+            }
+        };
+        let NodeKind::Leaf(symbol) = self.tree.nodes[leaf].kind else {
+            unreachable!()
+        };
+        U::update(&mut self.tree, leaf);
+        Ok(symbol)
+    }
+}
+
+pub(crate) fn adaptive_encode_symbols<S, U, I>(symbols: I) -> EncodedData
+where
+    S: RawSymbol,
+    U: UpdateRule,
+    I: IntoIterator<Item = S>,
+{
+    let mut coder = AdaptiveHuffman::<S, U>::new();
+    let mut encoded_data = EncodedData::new();
+    for symbol in symbols {
+        coder.encode_symbol(symbol, &mut encoded_data);
+    }
+    encoded_data
+}
+
+pub(crate) fn adaptive_decode_symbols<S, U>(
+    encoded_data: &EncodedData,
+) -> Result<Vec<S>, HuffmanError>
+where
+    S: RawSymbol,
+    U: UpdateRule,
+{
+    let mut coder = AdaptiveHuffman::<S, U>::new();
+    let mut bits = encoded_data.bits().peekable();
+    let mut result = Vec::new();
+    while bits.peek().is_some() {
+        result.push(coder.decode_symbol(&mut bits)?);
+    }
+    Ok(result)
+}
+
+pub(crate) fn adaptive_encode<U: UpdateRule>(text: &str) -> EncodedData {
+    adaptive_encode_symbols::<_, U, _>(text.chars())
+}
+
+pub(crate) fn adaptive_decode<U: UpdateRule>(
+    encoded_data: &EncodedData,
+) -> Result<String, HuffmanError> {
+    adaptive_decode_symbols::<char, U>(encoded_data).map(|chars| chars.into_iter().collect())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::huffman::{create_huffman_encoding, encode};
+
+    // Checks the sibling property, and for Vitter's algorithm also that leaves
+    // come after internal nodes of the same weight.
+    fn check_invariants<S: Hash + Eq + Copy + std::fmt::Debug>(
+        tree: &AdaptiveTree<S>,
+        leaves_last: bool,
+    ) {
+        for (index, node) in tree.nodes.iter().enumerate() {
+            if let NodeKind::Internal { left, right } = node.kind {
+                assert_eq!(left.abs_diff(right), 1);
+                assert_eq!(tree.nodes[left].parent, index);
+                assert_eq!(tree.nodes[right].parent, index);
+                assert_eq!(
+                    node.weight,
+                    tree.nodes[left].weight + tree.nodes[right].weight
+                );
+            }
+            if let Some(next) = tree.nodes.get(index + 1) {
+                assert!(node.weight >= next.weight);
+                if leaves_last && node.weight == next.weight {
+                    assert!(!tree.is_leaf(index) || tree.is_leaf(index + 1));
+                }
+            }
+        }
+        assert_eq!(tree.nodes[tree.nyt].kind, NodeKind::Nyt);
+        for (&symbol, &leaf) in tree.leaves.iter() {
+            assert_eq!(tree.nodes[leaf].kind, NodeKind::Leaf(symbol));
+        }
+    }
+
+    fn round_trip<U: UpdateRule>(text: &str, leaves_last: bool) -> u64 {
+        let mut encoder = AdaptiveHuffman::<char, U>::new();
+        let mut encoded = EncodedData::new();
+        for c in text.chars() {
+            encoder.encode_symbol(c, &mut encoded);
+            check_invariants(&encoder.tree, leaves_last);
+        }
+        assert_eq!(adaptive_decode::<U>(&encoded).unwrap(), text);
+        encoded.bit_len()
+    }
+
+    #[test]
+    fn round_trips() {
+        for text in [
+            "",
+            "a",
+            "aaaaaaaa",
+            "abracadabra",
+            "This is test data for encoding and decoding! Ünïcödé too.",
+        ] {
+            round_trip::<Fgk>(text, false);
+            round_trip::<Vitter>(text, true);
+        }
+
+        let data = (0..5000u32)
+            .map(|i| (i * i % 251) as u8)
+            .collect::<Vec<_>>();
+        let encoded = adaptive_encode_symbols::<_, Vitter, _>(data.iter().copied());
+        assert_eq!(
+            adaptive_decode_symbols::<u8, Vitter>(&encoded).unwrap(),
+            data
+        );
+
+        let truncated = EncodedData {
+            data: encoded.data[..encoded.data.len() - 1].to_vec(),
+            last_byte_len: 0,
+        };
+        assert_eq!(
+            adaptive_decode_symbols::<u8, Vitter>(&truncated),
+            Err(HuffmanError::TruncatedStream)
+        );
+    }
+
+    #[test]
+    fn close_to_static_huffman() {
+        let text = "It was the best of times, it was the worst of times, it was the age of \
+                    wisdom, it was the age of foolishness, it was the epoch of belief, it was \
+                    the epoch of incredulity, it was the season of Light, it was the season of \
+                    Darkness."
+            .repeat(4);
+        let fgk = round_trip::<Fgk>(&text, false);
+        let vitter = round_trip::<Vitter>(&text, true);
+        let encoding = create_huffman_encoding(&text).unwrap();
+        let static_bits = encode(&encoding, &text).unwrap().bit_len();
+        let distinct = encoding.code_lengths().len() as u64;
+        let symbols = text.chars().count() as u64;
+
+        assert!(vitter <= fgk);
+        // Vitter's bound, plus the raw bits of every escaped symbol.
+        assert!(vitter <= static_bits + symbols + distinct * char::BITS as u64);
+    }
+}
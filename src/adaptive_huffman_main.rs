@@ -0,0 +1,26 @@
+use adaptive_huffman::{adaptive_decode, adaptive_encode, Fgk, Vitter};
+use huffman::{create_huffman_encoding, encode};
+
+mod adaptive_huffman;
+mod huffman;
+
+fn main() {
+    let text = "This is test data for generating a huffman encoding! \
+                It is sent in one pass, without a code table."
+        .repeat(10);
+
+    let encoding = create_huffman_encoding(&text).unwrap();
+    let static_bits = encode(&encoding, &text).unwrap().bit_len();
+    println!(
+        "{:<8} {:>6} bits, plus the code table",
+        "static", static_bits
+    );
+
+    let encoded_data = adaptive_encode::<Fgk>(&text);
+    println!("{:<8} {:>6} bits", "FGK", encoded_data.bit_len());
+    assert_eq!(adaptive_decode::<Fgk>(&encoded_data).unwrap(), text);
+
+    let encoded_data = adaptive_encode::<Vitter>(&text);
+    println!("{:<8} {:>6} bits", "Vitter", encoded_data.bit_len());
+    assert_eq!(adaptive_decode::<Vitter>(&encoded_data).unwrap(), text);
+}
@@ -0,0 +1,34 @@
+use std::time::Duration;
+
+// Shared by the benchmarks and the tests that need reproducible
+// pseudo-random input without pulling in a dependency.
+
+pub(crate) const DEFAULT_SEED: u64 = 0x2545_f491_4f6c_dd1d;
+
+pub(crate) struct XorShift {
+    state: u64,
+}
+
+impl XorShift {
+    pub(crate) fn new(seed: u64) -> Self {
+        assert_ne!(seed, 0, "xorshift gets stuck at zero");
+        Self { state: seed }
+    }
+
+    pub(crate) fn next_u64(&mut self) -> u64 {
+        self.state ^= self.state << 13;
+        self.state ^= self.state >> 7;
+        self.state ^= self.state << 17;
+        self.state
+    }
+}
+
+impl Default for XorShift {
+    fn default() -> Self {
+        Self::new(DEFAULT_SEED)
+    }
+}
+
+pub(crate) fn format_duration(duration: Duration) -> String {
+    format!("{:.2}ms", duration.as_secs_f64() * 1000.0)
+}
@@ -0,0 +1,212 @@
+use std::collections::HashMap;
+
+use crate::graph::{sorted_nodes, undirected_edges, WeightedGraph};
+
+#[derive(Debug, PartialEq, Eq)]
+pub(crate) struct BiconnectedComponent<'graph> {
+    pub(crate) nodes: Vec<&'graph str>,
+    pub(crate) edges: Vec<(&'graph str, &'graph str)>,
+}
+
+#[derive(Debug, PartialEq, Eq)]
+pub(crate) struct Biconnectivity<'graph> {
+    pub(crate) articulation_points: Vec<&'graph str>,
+    pub(crate) bridges: Vec<(&'graph str, &'graph str)>,
+    pub(crate) components: Vec<BiconnectedComponent<'graph>>,
+}
+
+struct Frame {
+    node: usize,
+    parent: Option<usize>,
+    next_neighbor: usize,
+}
+
+const UNVISITED: usize = usize::MAX;
+
+// Hopcroft-Tarjan over the undirected view of the graph. The DFS is driven by an
+// explicit stack so that long paths cannot overflow the call stack.
+pub(crate) fn biconnectivity<G: WeightedGraph>(graph: &G) -> Biconnectivity<'_> {
+    let nodes = sorted_nodes(graph);
+    let indices = nodes
+        .iter()
+        .enumerate()
+        .map(|(index, node)| (*node, index))
+        .collect::<HashMap<_, _>>();
+    let mut adjacency = vec![Vec::new(); nodes.len()];
+    for (from, to, _) in undirected_edges(graph) {
+        adjacency[indices[from]].push(indices[to]);
+        adjacency[indices[to]].push(indices[from]);
+    }
+    for neighbors in adjacency.iter_mut() {
+        neighbors.sort_unstable();
+    }
+
+    let mut discovered = vec![UNVISITED; nodes.len()];
+    let mut low = vec![0; nodes.len()];
+    let mut is_articulation_point = vec![false; nodes.len()];
+    let mut bridges = Vec::new();
+    let mut components = Vec::new();
+    let mut time = 0;
+    let mut stack = Vec::new();
+    let mut edge_stack = Vec::new();
+
+    for root in 0..nodes.len() {
+        if discovered[root] != UNVISITED {
+            continue;
+        }
+        discovered[root] = time;
+        low[root] = time;
+        time += 1;
+        let mut root_children = 0;
+        stack.push(Frame {
+            node: root,
+            parent: None,
+            next_neighbor: 0,
+        });
+        while let Some(frame) = stack.last_mut() {
+            let node = frame.node;
+            let parent = frame.parent;
+            if let Some(&neighbor) = adjacency[node].get(frame.next_neighbor) {
+                frame.next_neighbor += 1;
+                if discovered[neighbor] == UNVISITED {
+                    if node == root {
+                        root_children += 1;
+                    }
+                    edge_stack.push((node, neighbor));
+                    discovered[neighbor] = time;
+                    low[neighbor] = time;
+                    time += 1;
+                    stack.push(Frame {
+                        node: neighbor,
+                        parent: Some(node),
+                        next_neighbor: 0,
+                    });
+                } else if Some(neighbor) != parent && discovered[neighbor] < discovered[node] {
+                    edge_stack.push((node, neighbor));
+                    low[node] = low[node].min(discovered[neighbor]);
+                }
+                continue;
+            }
+
+            stack.pop();
+            let Some(parent) = parent else {
+                continue;
+            };
+            low[parent] = low[parent].min(low[node]);
+            if low[node] > discovered[parent] {
+                bridges.push(ordered(nodes[parent], nodes[node]));
+            }
+            if low[node] >= discovered[parent] {
+                if parent != root {
+                    is_articulation_point[parent] = true;
+                }
+                let mut component_edges = Vec::new();
+                while let Some(edge) = edge_stack.pop() {
+                    component_edges.push(edge);
+                    if edge == (parent, node) {
+                        break;
+                    }
+                }
+                components.push(component(&nodes, component_edges));
+            }
+        }
+        if root_children > 1 {
+            is_articulation_point[root] = true;
+        }
+    }
+
+    bridges.sort_unstable();
+    Biconnectivity {
+        articulation_points: nodes
+            .iter()
+            .zip(is_articulation_point)
+            .filter_map(|(node, is_articulation_point)| is_articulation_point.then_some(*node))
+            .collect(),
+        bridges,
+        components,
+    }
+}
+
+fn ordered<'graph>(a: &'graph str, b: &'graph str) -> (&'graph str, &'graph str) {
+    if a < b {
+        (a, b)
+    } else {
+        (b, a)
+    }
+}
+
+fn component<'graph>(
+    nodes: &[&'graph str],
+    edges: Vec<(usize, usize)>,
+) -> BiconnectedComponent<'graph> {
+    let mut component_nodes = edges
+        .iter()
+        .flat_map(|&(from, to)| [nodes[from], nodes[to]])
+        .collect::<Vec<_>>();
+    component_nodes.sort_unstable();
+    component_nodes.dedup();
+    let mut component_edges = edges
+        .into_iter()
+        .map(|(from, to)| ordered(nodes[from], nodes[to]))
+        .collect::<Vec<_>>();
+    component_edges.sort_unstable();
+    BiconnectedComponent {
+        nodes: component_nodes,
+        edges: component_edges,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::dijkstra::Graph;
+    use crate::network_flow::NetworkGraph;
+
+    #[test]
+    fn finds_articulation_points_and_bridges() {
+        // Two triangles joined by the bridge c - d, with a pendant node e on d.
+        let mut graph = Graph::new();
+        graph.add_edge("a", "b", 1);
+        graph.add_edge("b", "c", 1);
+        graph.add_edge("c", "a", 1);
+        graph.add_edge("c", "d", 1);
+        graph.add_edge("d", "f", 1);
+        graph.add_edge("f", "g", 1);
+        graph.add_edge("g", "d", 1);
+        graph.add_edge("d", "e", 1);
+        let result = biconnectivity(&graph);
+        assert_eq!(result.articulation_points, vec!["c", "d"]);
+        assert_eq!(result.bridges, vec![("c", "d"), ("d", "e")]);
+        let mut components = result
+            .components
+            .iter()
+            .map(|component| component.nodes.clone())
+            .collect::<Vec<_>>();
+        components.sort();
+        assert_eq!(
+            components,
+            vec![
+                vec!["a", "b", "c"],
+                vec!["c", "d"],
+                vec!["d", "e"],
+                vec!["d", "f", "g"]
+            ]
+        );
+    }
+
+    #[test]
+    fn cycle_has_no_cut_vertices() {
+        let mut graph = NetworkGraph::new();
+        graph.add_edge("s", "a", 3);
+        graph.add_edge("a", "b", 3);
+        graph.add_edge("b", "t", 3);
+        graph.add_edge("s", "t", 3);
+        graph.add_node("lonely");
+        let result = biconnectivity(&graph);
+        assert!(result.articulation_points.is_empty());
+        assert!(result.bridges.is_empty());
+        assert_eq!(result.components.len(), 1);
+        assert_eq!(result.components[0].nodes, vec!["a", "b", "s", "t"]);
+        assert_eq!(result.components[0].edges.len(), 4);
+    }
+}
@@ -0,0 +1,35 @@
+mod biconnected;
+mod dijkstra;
+mod graph;
+mod network_flow;
+
+use biconnected::biconnectivity;
+use dijkstra::Graph;
+
+fn construct_graph() -> Graph {
+    let mut graph = Graph::new();
+    graph.add_edge("router-a", "router-b", 1);
+    graph.add_edge("router-b", "router-c", 1);
+    graph.add_edge("router-c", "router-a", 1);
+
+    // The only link between the two halves of the network.
+    graph.add_edge("router-c", "gateway", 1);
+
+    graph.add_edge("gateway", "switch-a", 1);
+    graph.add_edge("gateway", "switch-b", 1);
+    graph.add_edge("switch-a", "switch-b", 1);
+
+    graph.add_edge("switch-b", "printer", 1);
+
+    graph
+}
+
+fn main() {
+    let graph = construct_graph();
+    let result = biconnectivity(&graph);
+    println!("Articulation points: {:?}", result.articulation_points);
+    println!("Bridges: {:?}", result.bridges);
+    for component in result.components.iter() {
+        println!("Biconnected component: {:?}", component.nodes);
+    }
+}
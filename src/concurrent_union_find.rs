@@ -0,0 +1,253 @@
+use std::fmt::{Debug, Formatter, Result as FmtResult};
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+use crate::union_find::{UnionFind, UnionFindBySizeWithCompression};
+
+// splitmix64, used to give every element a fixed pseudo random priority.
+fn priority(x: usize) -> u64 {
+    let mut z = (x as u64).wrapping_add(0x9e37_79b9_7f4a_7c15);
+    z = (z ^ (z >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+    z = (z ^ (z >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+    z ^ (z >> 31)
+}
+
+// Lock-free union-find in the style of Anderson and Woll. Roots point at
+// themselves and are only ever linked with a compare-and-swap, always from the
+// lower priority root to the higher one, so no cycles can form no matter how
+// operations interleave. Finds compress paths by halving, which is safe to race
+// because it only ever replaces a parent with one of its ancestors.
+pub(crate) struct ConcurrentUnionFind {
+    parents: Box<[AtomicUsize]>,
+    starting_index: usize,
+}
+
+impl Debug for ConcurrentUnionFind {
+    fn fmt(&self, f: &mut Formatter<'_>) -> FmtResult {
+        f.debug_list()
+            .entries(
+                self.parents
+                    .iter()
+                    .map(|parent| parent.load(Ordering::Relaxed) + self.starting_index),
+            )
+            .finish()
+    }
+}
+
+impl ConcurrentUnionFind {
+    pub(crate) fn new(n: usize, starting_index: usize) -> Self {
+        Self {
+            parents: (0..n).map(AtomicUsize::new).collect(),
+            starting_index,
+        }
+    }
+
+    fn find_internal(&self, mut x: usize) -> usize {
+        loop {
+            let parent = self.parents[x].load(Ordering::Acquire);
+            if parent == x {
+                return x;
+            }
+            let grandparent = self.parents[parent].load(Ordering::Acquire);
+            if parent != grandparent {
+                // Losing this race only means someone else already shortened
+                // the path.
+                let _ = self.parents[x].compare_exchange_weak(
+                    parent,
+                    grandparent,
+                    Ordering::AcqRel,
+                    Ordering::Relaxed,
+                );
+            }
+            x = grandparent;
+        }
+    }
+
+    pub(crate) fn find(&self, x: usize) -> usize {
+        assert!(
+            x >= self.starting_index && x - self.starting_index < self.parents.len(),
+            "out of bounds"
+        );
+        self.find_internal(x - self.starting_index) + self.starting_index
+    }
+
+    // Returns whether this call merged two different sets.
+    pub(crate) fn union(&self, root_1: usize, root_2: usize) -> bool {
+        assert!(
+            root_1 >= self.starting_index && root_1 - self.starting_index < self.parents.len(),
+            "out of bounds"
+        );
+        assert!(
+            root_2 >= self.starting_index && root_2 - self.starting_index < self.parents.len(),
+            "out of bounds"
+        );
+        let mut root_1 = root_1 - self.starting_index;
+        let mut root_2 = root_2 - self.starting_index;
+        loop {
+            root_1 = self.find_internal(root_1);
+            root_2 = self.find_internal(root_2);
+            if root_1 == root_2 {
+                return false;
+            }
+            let (lower, higher) = if (priority(root_1), root_1) < (priority(root_2), root_2) {
+                (root_1, root_2)
+            } else {
+                (root_2, root_1)
+            };
+            // Fails if `lower` stopped being a root since we found it, in
+            // which case we start over from the new roots.
+            if self.parents[lower]
+                .compare_exchange(lower, higher, Ordering::AcqRel, Ordering::Relaxed)
+                .is_ok()
+            {
+                return true;
+            }
+        }
+    }
+
+    pub(crate) fn same_set(&self, a: usize, b: usize) -> bool {
+        let mut a = self.find(a) - self.starting_index;
+        let mut b = self.find(b) - self.starting_index;
+        loop {
+            if a == b {
+                return true;
+            }
+            // If `a` is still a root then the two roots were distinct at the
+            // moment we looked, otherwise a union raced us and we retry.
+            if self.parents[a].load(Ordering::Acquire) == a {
+                return false;
+            }
+            a = self.find_internal(a);
+            b = self.find_internal(b);
+        }
+    }
+
+    pub(crate) fn num_sets(&self) -> usize {
+        self.parents
+            .iter()
+            .enumerate()
+            .filter(|(index, parent)| parent.load(Ordering::Acquire) == *index)
+            .count()
+    }
+
+    pub(crate) fn len(&self) -> usize {
+        self.parents.len()
+    }
+
+    pub(crate) fn is_empty(&self) -> bool {
+        self.parents.is_empty()
+    }
+
+    // Same numbering as `UnionFind::component_labels`. Only meaningful once
+    // concurrent unions have finished.
+    pub(crate) fn component_labels(&self) -> Vec<usize> {
+        const UNLABELLED: usize = usize::MAX;
+        let mut root_labels = vec![UNLABELLED; self.parents.len()];
+        let mut next_label = 0;
+        (0..self.parents.len())
+            .map(|x| {
+                let root = self.find_internal(x);
+                if root_labels[root] == UNLABELLED {
+                    root_labels[root] = next_label;
+                    next_label += 1;
+                }
+                root_labels[root]
+            })
+            .collect()
+    }
+
+    // Copies the current partition into a sequential union-find. Only
+    // meaningful once concurrent unions have finished.
+    pub(crate) fn to_union_find(&self) -> UnionFindBySizeWithCompression {
+        let mut nodes = vec![0isize; self.parents.len()];
+        for x in 0..self.parents.len() {
+            let root = self.find_internal(x);
+            if root != x {
+                nodes[x] = root as isize;
+            }
+            nodes[root] -= 1;
+        }
+        UnionFindBySizeWithCompression::from_nodes_and_starting_index(
+            nodes.into_boxed_slice(),
+            self.starting_index,
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::bench_util::XorShift;
+    use std::thread;
+
+    // Spreads the small loop counter over the whole state.
+    const SEED_MULTIPLIER: u64 = 0x2545_f491_4f6c_dd1d;
+
+    fn random_pairs(n: usize, count: usize, seed: u64) -> Vec<(usize, usize)> {
+        let mut rng = XorShift::new(seed);
+        let mut next = || rng.next_u64() as usize % n;
+        (0..count).map(|_| (next(), next())).collect()
+    }
+
+    #[test]
+    fn sequential_behaviour() {
+        let uf = ConcurrentUnionFind::new(6, 1);
+        assert!(uf.union(1, 2));
+        assert!(uf.union(3, 4));
+        assert!(!uf.union(2, 1));
+        assert!(uf.union(4, 2));
+        assert!(uf.same_set(1, 3));
+        assert!(!uf.same_set(1, 5));
+        assert_eq!(uf.find(1), uf.find(4));
+        assert_eq!(uf.num_sets(), 3);
+        let mut sequential = uf.to_union_find();
+        assert_eq!(sequential.set_size(2), 4);
+        assert_eq!(sequential.find(6), 6);
+        assert_eq!(uf.component_labels(), sequential.component_labels());
+    }
+
+    #[test]
+    fn stress_against_sequential() {
+        const ELEMENTS: usize = 20_000;
+        const THREADS: usize = 8;
+        for seed in 1..=5u64 {
+            let pairs = random_pairs(ELEMENTS, 15_000, seed * SEED_MULTIPLIER);
+            let uf = ConcurrentUnionFind::new(ELEMENTS, 0);
+            let merges = thread::scope(|scope| {
+                let handles = pairs
+                    .chunks(pairs.len() / THREADS)
+                    .map(|chunk| {
+                        let uf = &uf;
+                        scope.spawn(move || {
+                            let mut merges = 0;
+                            for &(a, b) in chunk {
+                                if uf.union(a, b) {
+                                    merges += 1;
+                                }
+                                uf.find(b);
+                                uf.same_set(a, (a + b) % ELEMENTS);
+                            }
+                            merges
+                        })
+                    })
+                    .collect::<Vec<_>>();
+                handles
+                    .into_iter()
+                    .map(|handle| handle.join().unwrap())
+                    .sum::<usize>()
+            });
+
+            let mut expected = UnionFindBySizeWithCompression::new(ELEMENTS, 0);
+            for &(a, b) in pairs.iter() {
+                expected.union(a, b);
+            }
+            assert_eq!(uf.num_sets(), expected.num_sets());
+            assert_eq!(merges, ELEMENTS - expected.num_sets());
+            for x in 0..ELEMENTS {
+                let root = expected.find(x);
+                assert!(uf.same_set(x, root));
+                assert_eq!(uf.find(x), uf.find(root));
+            }
+            assert_eq!(uf.to_union_find().num_sets(), expected.num_sets());
+        }
+    }
+}
@@ -0,0 +1,42 @@
+use concurrent_union_find::ConcurrentUnionFind;
+use std::thread;
+
+#[cfg(test)]
+mod bench_util;
+mod concurrent_union_find;
+mod union_find;
+
+// Labels the connected components of a grid of blocks separated by empty rows
+// and columns, with each thread taking a band of rows.
+fn main() {
+    const WIDTH: usize = 64;
+    const HEIGHT: usize = 64;
+    const THREADS: usize = 4;
+    let grid = (0..WIDTH * HEIGHT)
+        .map(|i| i % WIDTH % 9 != 4 && i / WIDTH % 7 != 3)
+        .collect::<Vec<_>>();
+    let uf = ConcurrentUnionFind::new(WIDTH * HEIGHT, 0);
+    thread::scope(|scope| {
+        for band in 0..THREADS {
+            let (uf, grid) = (&uf, &grid);
+            scope.spawn(move || {
+                for y in band * HEIGHT / THREADS..(band + 1) * HEIGHT / THREADS {
+                    for x in 0..WIDTH {
+                        let i = y * WIDTH + x;
+                        if !grid[i] {
+                            continue;
+                        }
+                        if x + 1 < WIDTH && grid[i + 1] {
+                            uf.union(i, i + 1);
+                        }
+                        if y + 1 < HEIGHT && grid[i + WIDTH] {
+                            uf.union(i, i + WIDTH);
+                        }
+                    }
+                }
+            });
+        }
+    });
+    let empty_cells = grid.iter().filter(|cell| !**cell).count();
+    println!("Number of components: {}", uf.num_sets() - empty_cells);
+}
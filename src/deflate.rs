@@ -0,0 +1,618 @@
+use std::collections::HashMap;
+use std::error::Error;
+use std::fmt::{Display, Formatter, Result as FmtResult};
+
+use crate::huffman::{length_limited_code_lengths, HuffmanEncoding, Steps};
+use crate::lz77::{MatchFinder, Token};
+
+// Raw DEFLATE (RFC 1951), as found inside zlib and gzip streams.
+
+const END_OF_BLOCK: u16 = 256;
+const LENGTH_BASE: [u16; 29] = [
+    3, 4, 5, 6, 7, 8, 9, 10, 11, 13, 15, 17, 19, 23, 27, 31, 35, 43, 51, 59, 67, 83, 99, 115, 131,
+    163, 195, 227, 258,
+];
+const LENGTH_EXTRA: [u8; 29] = [
+    0, 0, 0, 0, 0, 0, 0, 0, 1, 1, 1, 1, 2, 2, 2, 2, 3, 3, 3, 3, 4, 4, 4, 4, 5, 5, 5, 5, 0,
+];
+const DISTANCE_BASE: [u16; 30] = [
+    1, 2, 3, 4, 5, 7, 9, 13, 17, 25, 33, 49, 65, 97, 129, 193, 257, 385, 513, 769, 1025, 1537,
+    2049, 3073, 4097, 6145, 8193, 12289, 16385, 24577,
+];
+const DISTANCE_EXTRA: [u8; 30] = [
+    0, 0, 0, 0, 1, 1, 2, 2, 3, 3, 4, 4, 5, 5, 6, 6, 7, 7, 8, 8, 9, 9, 10, 10, 11, 11, 12, 12, 13,
+    13,
+];
+// The order code length code lengths are sent in, least likely to be used
+// last so that they can be left out.
+const CODE_LENGTH_ORDER: [usize; 19] = [
+    16, 17, 18, 0, 8, 7, 9, 6, 10, 5, 11, 4, 12, 3, 13, 2, 14, 1, 15,
+];
+const LITERAL_LENGTH_CODES: usize = 286;
+const DISTANCE_CODES: usize = 30;
+const MAX_CODE_LEN: u8 = 15;
+const MAX_CODE_LENGTH_CODE_LEN: u8 = 7;
+const MAX_STORED_LEN: usize = u16::MAX as usize;
+// Long enough to amortize the code tables, short enough to follow changes in
+// the data.
+const BLOCK_TOKENS: usize = 1 << 14;
+
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub(crate) enum DeflateError {
+    TruncatedStream,
+    InvalidBlockType,
+    InvalidStoredLength,
+    InvalidCodeLengths,
+    InvalidCode,
+    DistanceTooFar { distance: usize, available: usize },
+}
+
+impl Display for DeflateError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> FmtResult {
+        match self {
+            DeflateError::TruncatedStream => write!(f, "the stream ends too early"),
+            DeflateError::InvalidBlockType => write!(f, "a block has the reserved type 3"),
+            DeflateError::InvalidStoredLength => {
+                write!(f, "a stored block's length does not match its complement")
+            }
+            DeflateError::InvalidCodeLengths => {
+                write!(f, "a block's code lengths do not form a prefix code")
+            }
+            DeflateError::InvalidCode => write!(f, "the data contains an unassigned code"),
+            DeflateError::DistanceTooFar {
+                distance,
+                available,
+            } => write!(
+                f,
+                "a match refers {distance} bytes back but only {available} have been produced"
+            ),
+        }
+    }
+}
+
+impl Error for DeflateError {}
+
+// DEFLATE packs bits starting from the least significant bit of each byte,
+// with Huffman codes going in from their first bit and everything else from
+// its least significant bit.
+#[derive(Default)]
+struct BitWriter {
+    output: Vec<u8>,
+    bits: u64,
+    len: u32,
+}
+
+impl BitWriter {
+    fn write_bits(&mut self, value: u32, len: u8) {
+        self.bits |= (value as u64) << self.len;
+        self.len += len as u32;
+        while self.len >= 8 {
+            self.output.push(self.bits as u8);
+            self.bits >>= 8;
+            self.len -= 8;
+        }
+    }
+
+    fn write_code(&mut self, steps: Steps) {
+        self.write_bits(
+            steps.bits.reverse_bits() >> (u32::BITS - steps.len as u32),
+            steps.len,
+        );
+    }
+
+    fn align(&mut self) {
+        if self.len > 0 {
+            self.write_bits(0, 8 - self.len as u8);
+        }
+    }
+
+    fn bit_len(&self) -> usize {
+        self.output.len() * 8 + self.len as usize
+    }
+
+    fn append(&mut self, other: BitWriter) {
+        for &byte in &other.output {
+            self.write_bits(byte as u32, 8);
+        }
+        self.write_bits(other.bits as u32, other.len as u8);
+    }
+
+    fn finish(mut self) -> Vec<u8> {
+        self.align();
+        self.output
+    }
+}
+
+struct BitReader<'data> {
+    input: &'data [u8],
+    position: usize,
+}
+
+impl<'data> BitReader<'data> {
+    fn new(input: &'data [u8]) -> Self {
+        Self { input, position: 0 }
+    }
+
+    fn read_bit(&mut self) -> Result<bool, DeflateError> {
+        let byte = self
+            .input
+            .get(self.position / 8)
+            .ok_or(DeflateError::TruncatedStream)?;
+        let bit = byte >> (self.position % 8) & 1 != 0;
+        self.position += 1;
+        Ok(bit)
+    }
+
+    fn read_bits(&mut self, len: u8) -> Result<u32, DeflateError> {
+        let mut value = 0;
+        for shift in 0..len {
+            value |= (self.read_bit()? as u32) << shift;
+        }
+        Ok(value)
+    }
+
+    fn align(&mut self) {
+        self.position = self.position.next_multiple_of(8);
+    }
+
+    fn read_bytes(&mut self, len: usize) -> Result<&'data [u8], DeflateError> {
+        let start = self.position / 8;
+        let bytes = self
+            .input
+            .get(start..start + len)
+            .ok_or(DeflateError::TruncatedStream)?;
+        self.position += len * 8;
+        Ok(bytes)
+    }
+}
+
+fn length_code(length: u16) -> usize {
+    LENGTH_BASE.partition_point(|&base| base <= length) - 1
+}
+
+fn distance_code(distance: u16) -> usize {
+    DISTANCE_BASE.partition_point(|&base| base <= distance) - 1
+}
+
+// Code lengths for every symbol below `frequencies.len()`, zero for unused
+// ones.
+fn code_lengths(frequencies: &[u64], max_len: u8) -> Vec<u8> {
+    let mut lengths = vec![0; frequencies.len()];
+    let used = frequencies
+        .iter()
+        .enumerate()
+        .filter(|&(_, &frequency)| frequency > 0)
+        .map(|(symbol, &frequency)| (symbol as u16, frequency));
+    // There are always at least two symbols' worth of room.
+    for (symbol, len) in length_limited_code_lengths(used, max_len).unwrap() {
+        lengths[symbol as usize] = len;
+    }
+    lengths
+}
+
+fn encoding_from_lengths(lengths: &[u8]) -> Option<HuffmanEncoding<u16>> {
+    HuffmanEncoding::from_code_lengths(
+        lengths
+            .iter()
+            .enumerate()
+            .filter(|&(_, &len)| len > 0)
+            .map(|(symbol, &len)| (symbol as u16, len)),
+    )
+}
+
+// Run-length encodes code lengths with the repeat codes 16 (the previous
+// length 3-6 times), 17 (3-10 zeros) and 18 (11-138 zeros), as
+// (symbol, extra bits value) pairs.
+fn run_length_encode(lengths: &[u8]) -> Vec<(u8, u8)> {
+    let mut symbols = Vec::new();
+    let mut index = 0;
+    while index < lengths.len() {
+        let len = lengths[index];
+        let run = lengths[index..].iter().take_while(|&&l| l == len).count();
+        let mut remaining = run;
+        if len == 0 {
+            while remaining >= 11 {
+                let count = remaining.min(138);
+                symbols.push((18, (count - 11) as u8));
+                remaining -= count;
+            }
+            if remaining >= 3 {
+                symbols.push((17, (remaining - 3) as u8));
+                remaining = 0;
+            }
+        } else if remaining >= 4 {
+            symbols.push((len, 0));
+            remaining -= 1;
+            while remaining >= 3 {
+                let count = remaining.min(6);
+                symbols.push((16, (count - 3) as u8));
+                remaining -= count;
+            }
+        }
+        symbols.extend(std::iter::repeat_n((len, 0), remaining));
+        index += run;
+    }
+    symbols
+}
+
+fn write_dynamic_block(writer: &mut BitWriter, tokens: &[Token], last: bool) {
+    let mut literal_frequencies = [0u64; LITERAL_LENGTH_CODES];
+    let mut distance_frequencies = [0u64; DISTANCE_CODES];
+    for &token in tokens {
+        match token {
+            Token::Literal(byte) => literal_frequencies[byte as usize] += 1,
+            Token::Match { length, distance } => {
+                literal_frequencies[257 + length_code(length)] += 1;
+                distance_frequencies[distance_code(distance)] += 1;
+            }
+        }
+    }
+    literal_frequencies[END_OF_BLOCK as usize] = 1;
+    // Some decoders reject a block without any distance codes, even when it
+    // has no matches.
+    if distance_frequencies.iter().all(|&frequency| frequency == 0) {
+        distance_frequencies[0] = 1;
+    }
+
+    let literal_lengths = code_lengths(&literal_frequencies, MAX_CODE_LEN);
+    let distance_lengths = code_lengths(&distance_frequencies, MAX_CODE_LEN);
+    let literal_count = 257.max(literal_lengths.iter().rposition(|&len| len > 0).unwrap() + 1);
+    let distance_count = distance_lengths.iter().rposition(|&len| len > 0).unwrap() + 1;
+
+    let mut all_lengths = literal_lengths[..literal_count].to_vec();
+    all_lengths.extend(&distance_lengths[..distance_count]);
+    let runs = run_length_encode(&all_lengths);
+    let mut code_length_frequencies = [0u64; 19];
+    for &(symbol, _) in &runs {
+        code_length_frequencies[symbol as usize] += 1;
+    }
+    let code_length_lengths = code_lengths(&code_length_frequencies, MAX_CODE_LENGTH_CODE_LEN);
+    let code_length_count = 4.max(
+        CODE_LENGTH_ORDER
+            .iter()
+            .rposition(|&symbol| code_length_lengths[symbol] > 0)
+            .unwrap()
+            + 1,
+    );
+
+    let literal_encoding = encoding_from_lengths(&literal_lengths).unwrap();
+    let distance_encoding = encoding_from_lengths(&distance_lengths).unwrap();
+    let code_length_encoding = encoding_from_lengths(&code_length_lengths).unwrap();
+
+    writer.write_bits(last as u32, 1);
+    writer.write_bits(2, 2);
+    writer.write_bits((literal_count - 257) as u32, 5);
+    writer.write_bits((distance_count - 1) as u32, 5);
+    writer.write_bits((code_length_count - 4) as u32, 4);
+    for &symbol in &CODE_LENGTH_ORDER[..code_length_count] {
+        writer.write_bits(code_length_lengths[symbol] as u32, 3);
+    }
+    for (symbol, extra) in runs {
+        writer.write_code(code_length_encoding.steps(symbol as u16).unwrap());
+        match symbol {
+            16 => writer.write_bits(extra as u32, 2),
+            17 => writer.write_bits(extra as u32, 3),
+            18 => writer.write_bits(extra as u32, 7),
+            _ => {}
+        }
+    }
+
+    for &token in tokens {
+        match token {
+            Token::Literal(byte) => {
+                writer.write_code(literal_encoding.steps(byte as u16).unwrap());
+            }
+            Token::Match { length, distance } => {
+                let code = length_code(length);
+                writer.write_code(literal_encoding.steps(257 + code as u16).unwrap());
+                writer.write_bits((length - LENGTH_BASE[code]) as u32, LENGTH_EXTRA[code]);
+                let code = distance_code(distance);
+                writer.write_code(distance_encoding.steps(code as u16).unwrap());
+                writer.write_bits(
+                    (distance - DISTANCE_BASE[code]) as u32,
+                    DISTANCE_EXTRA[code],
+                );
+            }
+        }
+    }
+    writer.write_code(literal_encoding.steps(END_OF_BLOCK).unwrap());
+}
+
+fn write_stored_blocks(writer: &mut BitWriter, data: &[u8], last: bool) {
+    let block_count = data.len().div_ceil(MAX_STORED_LEN);
+    for (index, chunk) in data.chunks(MAX_STORED_LEN).enumerate() {
+        writer.write_bits((last && index + 1 == block_count) as u32, 1);
+        writer.write_bits(0, 2);
+        writer.align();
+        writer.write_bits(chunk.len() as u32, 16);
+        writer.write_bits(!chunk.len() as u32 & 0xffff, 16);
+        for &byte in chunk {
+            writer.write_bits(byte as u32, 8);
+        }
+    }
+}
+
+fn token_len(token: Token) -> usize {
+    match token {
+        Token::Literal(_) => 1,
+        Token::Match { length, .. } => length as usize,
+    }
+}
+
+pub(crate) fn compress(data: &[u8]) -> Vec<u8> {
+    compress_with(data, &MatchFinder::default())
+}
+
+// Each block is written with its own Huffman codes, or stored as is when
+// that turns out smaller.
+pub(crate) fn compress_with(data: &[u8], match_finder: &MatchFinder) -> Vec<u8> {
+    let tokens = match_finder.tokens(data);
+    let mut writer = BitWriter::default();
+    if tokens.is_empty() {
+        write_dynamic_block(&mut writer, &[], true);
+        return writer.finish();
+    }
+    let mut start = 0;
+    let block_count = tokens.len().div_ceil(BLOCK_TOKENS);
+    for (index, block) in tokens.chunks(BLOCK_TOKENS).enumerate() {
+        let last = index + 1 == block_count;
+        let len = block.iter().map(|&token| token_len(token)).sum::<usize>();
+        let mut dynamic = BitWriter::default();
+        write_dynamic_block(&mut dynamic, block, last);
+        // Five bytes of header per stored block, plus up to a byte of
+        // padding.
+        let stored_bits = (len + 5 * len.div_ceil(MAX_STORED_LEN) + 1) * 8;
+        if dynamic.bit_len() <= stored_bits {
+            writer.append(dynamic);
+        } else {
+            write_stored_blocks(&mut writer, &data[start..start + len], last);
+        }
+        start += len;
+    }
+    writer.finish()
+}
+
+struct Decoder {
+    codes: HashMap<Steps, u16>,
+    max_len: u8,
+}
+
+impl Decoder {
+    fn new(lengths: &[u8]) -> Result<Self, DeflateError> {
+        let encoding = encoding_from_lengths(lengths).ok_or(DeflateError::InvalidCodeLengths)?;
+        Ok(Self {
+            codes: encoding
+                .codes()
+                .map(|(symbol, steps)| (steps, symbol))
+                .collect(),
+            max_len: encoding.max_code_len(),
+        })
+    }
+
+    fn fixed() -> (Self, Self) {
+        let mut literal_lengths = [8; 288];
+        literal_lengths[144..256].fill(9);
+        literal_lengths[256..280].fill(7);
+        (
+            Self::new(&literal_lengths).unwrap(),
+            Self::new(&[5; 32]).unwrap(),
+        )
+    }
+
+    fn decode(&self, reader: &mut BitReader) -> Result<u16, DeflateError> {
+        let mut steps = Steps::new();
+        while steps.len < self.max_len {
+            steps.bits = (steps.bits << 1) | reader.read_bit()? as u32;
+            steps.len += 1;
+            if let Some(&symbol) = self.codes.get(&steps) {
+                return Ok(symbol);
+            }
+        }
+        Err(DeflateError::InvalidCode)
+    }
+}
+
+fn read_dynamic_decoders(reader: &mut BitReader) -> Result<(Decoder, Decoder), DeflateError> {
+    let literal_count = reader.read_bits(5)? as usize + 257;
+    let distance_count = reader.read_bits(5)? as usize + 1;
+    let code_length_count = reader.read_bits(4)? as usize + 4;
+    let mut code_length_lengths = [0; 19];
+    for &symbol in &CODE_LENGTH_ORDER[..code_length_count] {
+        code_length_lengths[symbol] = reader.read_bits(3)? as u8;
+    }
+    let code_length_decoder = Decoder::new(&code_length_lengths)?;
+
+    let mut lengths = Vec::with_capacity(literal_count + distance_count);
+    while lengths.len() < literal_count + distance_count {
+        let (len, count) = match code_length_decoder.decode(reader)? {
+            len @ 0..=15 => (len as u8, 1),
+            16 => (
+                *lengths.last().ok_or(DeflateError::InvalidCodeLengths)?,
+                reader.read_bits(2)? + 3,
+            ),
+            17 => (0, reader.read_bits(3)? + 3),
+            _ => (0, reader.read_bits(7)? + 11),
+        };
+        lengths.extend(std::iter::repeat_n(len, count as usize));
+    }
+    if lengths.len() > literal_count + distance_count || lengths[END_OF_BLOCK as usize] == 0 {
+        return Err(DeflateError::InvalidCodeLengths);
+    }
+    Ok((
+        Decoder::new(&lengths[..literal_count])?,
+        Decoder::new(&lengths[literal_count..])?,
+    ))
+}
+
+fn inflate_block(
+    reader: &mut BitReader,
+    output: &mut Vec<u8>,
+    literals: &Decoder,
+    distances: &Decoder,
+) -> Result<(), DeflateError> {
+    loop {
+        let symbol = literals.decode(reader)?;
+        let code = match symbol {
+            0..=255 => {
+                output.push(symbol as u8);
+                continue;
+            }
+            END_OF_BLOCK => return Ok(()),
+            _ => symbol as usize - 257,
+        };
+        if code >= LENGTH_BASE.len() {
+            return Err(DeflateError::InvalidCode);
+        }
+        let length = LENGTH_BASE[code] as usize + reader.read_bits(LENGTH_EXTRA[code])? as usize;
+        let code = distances.decode(reader)? as usize;
+        if code >= DISTANCE_BASE.len() {
+            return Err(DeflateError::InvalidCode);
+        }
+        let distance =
+            DISTANCE_BASE[code] as usize + reader.read_bits(DISTANCE_EXTRA[code])? as usize;
+        if distance > output.len() {
+            return Err(DeflateError::DistanceTooFar {
+                distance,
+                available: output.len(),
+            });
+        }
+        let start = output.len() - distance;
+        for index in start..start + length {
+            output.push(output[index]);
+        }
+    }
+}
+
+// Decodes stored, fixed and dynamic blocks, so the output of other DEFLATE
+// implementations can be read too. Anything after the final block is ignored.
+pub(crate) fn decompress(data: &[u8]) -> Result<Vec<u8>, DeflateError> {
+    let mut reader = BitReader::new(data);
+    let mut output = Vec::new();
+    loop {
+        let last = reader.read_bit()?;
+        match reader.read_bits(2)? {
+            0 => {
+                reader.align();
+                let len = reader.read_bits(16)?;
+                if reader.read_bits(16)? != !len & 0xffff {
+                    return Err(DeflateError::InvalidStoredLength);
+                }
+                output.extend(reader.read_bytes(len as usize)?);
+            }
+            1 => {
+                let (literals, distances) = Decoder::fixed();
+                inflate_block(&mut reader, &mut output, &literals, &distances)?;
+            }
+            2 => {
+                let (literals, distances) = read_dynamic_decoders(&mut reader)?;
+                inflate_block(&mut reader, &mut output, &literals, &distances)?;
+            }
+            _ => return Err(DeflateError::InvalidBlockType),
+        }
+        if last {
+            return Ok(output);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::bench_util::XorShift;
+
+    fn sample_text() -> Vec<u8> {
+        (0..2000)
+            .map(|i| format!("INFO request {i} finished in {}ms\n", i * 37 % 1000))
+            .collect::<String>()
+            .into_bytes()
+    }
+
+    #[test]
+    fn round_trips() {
+        let text = sample_text();
+        let compressed = compress(&text);
+        assert!(compressed.len() < text.len() / 4, "{}", compressed.len());
+        assert_eq!(decompress(&compressed).unwrap(), text);
+
+        for data in [
+            &b""[..],
+            b"a",
+            b"aaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaa",
+            b"abcdefghijklmnopqrstuvwxyz",
+        ] {
+            assert_eq!(decompress(&compress(data)).unwrap(), data);
+        }
+
+        let window = MatchFinder::new(1024).with_max_chain(8);
+        assert_eq!(decompress(&compress_with(&text, &window)).unwrap(), text);
+    }
+
+    #[test]
+    fn stores_incompressible_data() {
+        let mut rng = XorShift::default();
+        let data = (0..100_000)
+            .map(|_| rng.next_u64() as u8)
+            .collect::<Vec<_>>();
+        let compressed = compress(&data);
+        // A stored block header for every block of tokens.
+        assert!(compressed.len() <= data.len() + 5 * data.len().div_ceil(BLOCK_TOKENS));
+        assert_eq!(decompress(&compressed).unwrap(), data);
+    }
+
+    #[test]
+    fn reads_zlib_output() {
+        // From Python's zlib with wbits=-15, which picks a fixed Huffman
+        // block for the short text and a dynamic one for the longer one.
+        let text = b"hello hello hello hello world\n".repeat(3);
+        let fixed = [
+            0xcb, 0x48, 0xcd, 0xc9, 0xc9, 0x57, 0xc8, 0xc0, 0x20, 0xcb, 0xf3, 0x8b, 0x72, 0x52,
+            0xb8, 0x32, 0x28, 0x90, 0x05, 0x00,
+        ];
+        assert_eq!(decompress(&fixed).unwrap(), text);
+
+        let mut text = b"The quick brown fox jumps over the lazy dog. ".repeat(2);
+        text.extend((b'a'..=b'z').cycle().take(26 * 3));
+        let dynamic = [
+            0x9d, 0xca, 0x47, 0x01, 0x80, 0x30, 0x0c, 0x00, 0x40, 0x2b, 0x51, 0x80, 0x9a, 0x1a,
+            0xe8, 0x9e, 0x34, 0xdd, 0x4b, 0x3d, 0x58, 0x80, 0xf7, 0x1d, 0x31, 0x12, 0x72, 0xb7,
+            0xdc, 0x03, 0x2b, 0x38, 0x23, 0x28, 0x5c, 0xe0, 0xfa, 0x9d, 0x2a, 0xe0, 0x90, 0x05,
+            0xda, 0xcb, 0x81, 0x9e, 0x0d, 0x02, 0xf5, 0x05, 0xe4, 0x4b, 0xa6, 0x8c, 0x0b, 0xa9,
+            0xb4, 0xb1, 0xce, 0x87, 0x3b, 0x62, 0xca, 0xa5, 0xb6, 0x3e, 0xe6, 0xda, 0xe7, 0x8f,
+            0x3c,
+        ];
+        assert_eq!(decompress(&dynamic).unwrap(), text);
+    }
+
+    #[test]
+    fn reports_errors() {
+        let compressed = compress(&sample_text());
+        assert_eq!(
+            decompress(&compressed[..compressed.len() / 2]).unwrap_err(),
+            DeflateError::TruncatedStream
+        );
+        assert_eq!(
+            decompress(&[0x07]).unwrap_err(),
+            DeflateError::InvalidBlockType
+        );
+        assert_eq!(
+            decompress(&[0x01, 0x01, 0x00, 0x00, 0x00]).unwrap_err(),
+            DeflateError::InvalidStoredLength
+        );
+        // A fixed block starting with a match of distance 1.
+        let mut writer = BitWriter::default();
+        writer.write_bits(1, 1);
+        writer.write_bits(1, 2);
+        writer.write_code(Steps {
+            bits: 0b0000001,
+            len: 7,
+        });
+        writer.write_code(Steps { bits: 0, len: 5 });
+        assert_eq!(
+            decompress(&writer.finish()).unwrap_err(),
+            DeflateError::DistanceTooFar {
+                distance: 1,
+                available: 0
+            }
+        );
+    }
+}
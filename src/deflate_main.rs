@@ -0,0 +1,43 @@
+use deflate::{compress, compress_with, decompress};
+use huffman::{create_byte_huffman_encoding, encode_bytes};
+use lz77::MatchFinder;
+use std::fs;
+
+#[cfg(test)]
+mod bench_util;
+mod deflate;
+mod huffman;
+mod lz77;
+
+fn main() {
+    let text = (0..100_000)
+        .map(|i| {
+            let level = if i % 17 == 0 { "WARN" } else { "INFO" };
+            format!("{level} request {i} finished in {}ms\n", i * 37 % 1000)
+        })
+        .collect::<String>()
+        .into_bytes();
+
+    let encoding = create_byte_huffman_encoding(&text).unwrap();
+    let huffman_bytes = encode_bytes(&encoding, &text).unwrap().data.len();
+    println!("{} bytes, {huffman_bytes} with Huffman alone", text.len());
+
+    for (name, match_finder) in [
+        ("default", MatchFinder::default()),
+        ("4KiB window", MatchFinder::new(4096)),
+        (
+            "greedy, short chains",
+            MatchFinder::default().with_max_chain(8).with_lazy_len(0),
+        ),
+    ] {
+        let compressed = compress_with(&text, &match_finder);
+        println!("{name:>22}: {} bytes", compressed.len());
+        assert_eq!(decompress(&compressed).unwrap(), text);
+    }
+
+    // Raw DEFLATE, e.g. python3 -c 'import sys, zlib;
+    // sys.stdout.buffer.write(zlib.decompress(open(sys.argv[1], "rb").read(), -15))'
+    let path = std::env::temp_dir().join("deflate_main.raw");
+    fs::write(&path, compress(&text)).unwrap();
+    println!("wrote {}", path.display());
+}
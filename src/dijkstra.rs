@@ -1,5 +1,7 @@
 use std::collections::{BinaryHeap, HashMap, HashSet};
 
+use crate::graph::WeightedGraph;
+
 pub(crate) struct Graph {
     nodes: HashMap<String, HashMap<String, u64>>,
 }
@@ -25,6 +27,20 @@ impl Graph {
     }
 }
 
+impl WeightedGraph for Graph {
+    fn nodes(&self) -> impl Iterator<Item = &str> {
+        self.nodes.keys().map(String::as_str)
+    }
+
+    fn neighbors(&self, node: &str) -> impl Iterator<Item = (&str, u64)> {
+        self.nodes
+            .get(node)
+            .into_iter()
+            .flatten()
+            .map(|(neighbor, weight)| (neighbor.as_str(), *weight))
+    }
+}
+
 struct QueueItem<'a>(u64, &'a str);
 
 impl PartialEq for QueueItem<'_> {
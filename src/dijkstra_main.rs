@@ -1,4 +1,5 @@
 mod dijkstra;
+mod graph;
 
 use dijkstra::{dijkstra, Graph};
 
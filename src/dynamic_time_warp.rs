@@ -1,4 +1,4 @@
-use ndarray::{ArrayD, IxDyn};
+use ndarray::{Array2, ArrayD, ArrayView1, ArrayView2, Axis, Ix2, IxDyn};
 
 pub(crate) fn dynamic_time_warp(s: &[i32], q: &[i32]) -> ArrayD<i32> {
     let mut dtw = ArrayD::zeros(IxDyn(&[s.len() + 1, q.len() + 1]));
@@ -18,12 +18,268 @@ pub(crate) fn dynamic_time_warp(s: &[i32], q: &[i32]) -> ArrayD<i32> {
                 i32::min(dtw[[i - 1, j]], dtw[[i, j - 1]]),
                 dtw[[i - 1, j - 1]],
             );
-            if min == dtw[[i - 1, j - 1]] {
-                println!("Found a match at i: {}, j: {}", i, j);
-            }
             dtw[[i, j]] = cost + min;
         }
     }
 
     dtw
 }
+
+// The cost of aligning two feature vectors. Any
+// `Fn(ArrayView1<f64>, ArrayView1<f64>) -> f64` closure works as one too.
+pub(crate) trait Distance {
+    fn distance(&self, a: ArrayView1<f64>, b: ArrayView1<f64>) -> f64;
+}
+
+pub(crate) struct Euclidean;
+
+impl Distance for Euclidean {
+    fn distance(&self, a: ArrayView1<f64>, b: ArrayView1<f64>) -> f64 {
+        a.iter()
+            .zip(&b)
+            .map(|(x, y)| (x - y) * (x - y))
+            .sum::<f64>()
+            .sqrt()
+    }
+}
+
+pub(crate) struct Manhattan;
+
+impl Distance for Manhattan {
+    fn distance(&self, a: ArrayView1<f64>, b: ArrayView1<f64>) -> f64 {
+        a.iter().zip(&b).map(|(x, y)| (x - y).abs()).sum()
+    }
+}
+
+// One minus the cosine similarity, so 0 for vectors pointing the same way and
+// 2 for opposite ones. A zero vector has no direction; it is at distance 0
+// from another zero vector and 1 from anything else.
+pub(crate) struct Cosine;
+
+impl Distance for Cosine {
+    fn distance(&self, a: ArrayView1<f64>, b: ArrayView1<f64>) -> f64 {
+        let norms = a.dot(&a).sqrt() * b.dot(&b).sqrt();
+        if norms == 0.0 {
+            if a.iter().chain(&b).all(|&x| x == 0.0) {
+                0.0
+            } else {
+                1.0
+            }
+        } else {
+            1.0 - a.dot(&b) / norms
+        }
+    }
+}
+
+impl<F> Distance for F
+where
+    F: Fn(ArrayView1<f64>, ArrayView1<f64>) -> f64,
+{
+    fn distance(&self, a: ArrayView1<f64>, b: ArrayView1<f64>) -> f64 {
+        self(a, b)
+    }
+}
+
+// Like `dynamic_time_warp`, over series whose rows are feature vectors. The
+// accumulated costs are laid out the same way, with an extra first row and
+// column that only the top left corner can be reached from.
+pub(crate) fn dynamic_time_warp_vectors<D: Distance>(
+    s: ArrayView2<f64>,
+    q: ArrayView2<f64>,
+    distance: D,
+) -> Array2<f64> {
+    assert_eq!(
+        s.ncols(),
+        q.ncols(),
+        "both series must have the same number of features"
+    );
+    let mut dtw = Array2::from_elem((s.nrows() + 1, q.nrows() + 1), f64::INFINITY);
+    dtw[[0, 0]] = 0.0;
+
+    for (i, a) in s.rows().into_iter().enumerate() {
+        for (j, b) in q.rows().into_iter().enumerate() {
+            let cost = distance.distance(a, b);
+            let min = dtw[[i, j + 1]].min(dtw[[i + 1, j]]).min(dtw[[i, j]]);
+            dtw[[i + 1, j + 1]] = cost + min;
+        }
+    }
+
+    dtw
+}
+
+// The best way to line up two series, found by walking back from the last
+// pair of elements through the accumulated costs.
+#[derive(Debug, Clone)]
+pub(crate) struct Alignment<T> {
+    pub(crate) distance: T,
+    // Pairs of indices into the two series, starting at (0, 0) and ending at
+    // the last element of each. Empty if either series is.
+    pub(crate) path: Vec<(usize, usize)>,
+    pub(crate) costs: Array2<T>,
+}
+
+impl<T: Copy + PartialOrd> Alignment<T> {
+    // Takes costs laid out like those of `dynamic_time_warp`. Ties go to the
+    // diagonal, which keeps the path as short as possible.
+    //
+    // If one series is empty nothing can be aligned, the path is empty and
+    // `distance` is the border value, `i32::MAX` or infinity for the costs
+    // computed here. Two empty series are at distance 0.
+    pub(crate) fn from_costs(costs: Array2<T>) -> Self {
+        let (rows, columns) = costs.dim();
+        assert!(
+            rows > 0 && columns > 0,
+            "costs need a border row and column, got a {rows}x{columns} matrix"
+        );
+        let distance = costs[[rows - 1, columns - 1]];
+        let mut path = Vec::new();
+        let (mut i, mut j) = (rows - 1, columns - 1);
+        while i > 0 && j > 0 {
+            path.push((i - 1, j - 1));
+            let mut next = (i - 1, j - 1);
+            for candidate in [(i - 1, j), (i, j - 1)] {
+                if costs[candidate] < costs[next] {
+                    next = candidate;
+                }
+            }
+            (i, j) = next;
+        }
+        path.reverse();
+        Self {
+            distance,
+            path,
+            costs,
+        }
+    }
+}
+
+impl<T> Alignment<T> {
+    // Both series stretched to the length of the path, repeating elements
+    // where the other series moves on without them.
+    pub(crate) fn warp<U: Clone>(&self, s: &[U], q: &[U]) -> (Vec<U>, Vec<U>) {
+        self.path
+            .iter()
+            .map(|&(i, j)| (s[i].clone(), q[j].clone()))
+            .unzip()
+    }
+
+    pub(crate) fn warp_rows<U: Clone>(
+        &self,
+        s: ArrayView2<U>,
+        q: ArrayView2<U>,
+    ) -> (Array2<U>, Array2<U>) {
+        let (s_indices, q_indices): (Vec<_>, Vec<_>) = self.path.iter().copied().unzip();
+        (s.select(Axis(0), &s_indices), q.select(Axis(0), &q_indices))
+    }
+}
+
+pub(crate) fn dynamic_time_warp_alignment(s: &[i32], q: &[i32]) -> Alignment<i32> {
+    let costs = dynamic_time_warp(s, q)
+        .into_dimensionality::<Ix2>()
+        .unwrap();
+    Alignment::from_costs(costs)
+}
+
+pub(crate) fn dynamic_time_warp_vectors_alignment<D: Distance>(
+    s: ArrayView2<f64>,
+    q: ArrayView2<f64>,
+    distance: D,
+) -> Alignment<f64> {
+    Alignment::from_costs(dynamic_time_warp_vectors(s, q, distance))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use ndarray::{array, s};
+
+    #[test]
+    fn matches_scalar_version() {
+        let s = [7, 5, 5, 1, 5, 3];
+        let q = [4, 2, 2, 2, 2];
+        let column =
+            |series: &[i32]| Array2::from_shape_fn((series.len(), 1), |(i, _)| series[i] as f64);
+        let expected = dynamic_time_warp(&s, &q);
+        for dtw in [
+            dynamic_time_warp_vectors(column(&s).view(), column(&q).view(), Euclidean),
+            dynamic_time_warp_vectors(column(&s).view(), column(&q).view(), Manhattan),
+        ] {
+            for i in 1..=s.len() {
+                for j in 1..=q.len() {
+                    assert_eq!(dtw[[i, j]], expected[[i, j]] as f64);
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn uses_distance() {
+        let s = array![[0.0, 0.0], [1.0, 1.0], [2.0, 0.0]];
+        let q = array![[0.0, 0.0], [2.0, 2.0], [2.0, 0.0], [2.0, 0.0]];
+        let dtw = dynamic_time_warp_vectors(s.view(), q.view(), Euclidean);
+        assert_eq!(dtw.dim(), (4, 5));
+        assert_eq!(dtw[[1, 0]], f64::INFINITY);
+        assert!((dtw[[3, 4]] - 2f64.sqrt()).abs() < 1e-12);
+        assert_eq!(
+            dynamic_time_warp_vectors(s.view(), q.view(), Manhattan)[[3, 4]],
+            2.0
+        );
+        // Only the directions differ at the second step.
+        let cosine = dynamic_time_warp_vectors(s.view(), q.view(), Cosine);
+        assert!(cosine[[3, 4]].abs() < 1e-12);
+        let chebyshev = |a: ArrayView1<f64>, b: ArrayView1<f64>| {
+            a.iter()
+                .zip(&b)
+                .map(|(x, y)| (x - y).abs())
+                .fold(0.0, f64::max)
+        };
+        assert_eq!(
+            dynamic_time_warp_vectors(s.view(), q.view(), chebyshev)[[3, 4]],
+            1.0
+        );
+    }
+
+    #[test]
+    fn finds_warping_path() {
+        let s = [7, 5, 5, 1, 5, 3];
+        let q = [4, 2, 2, 2, 2];
+        let alignment = dynamic_time_warp_alignment(&s, &q);
+        assert_eq!(alignment.distance, 11);
+        assert_eq!(alignment.path.first(), Some(&(0, 0)));
+        assert_eq!(alignment.path.last(), Some(&(5, 4)));
+        // Every step moves on in one series or both.
+        for window in alignment.path.windows(2) {
+            let ((i1, j1), (i2, j2)) = (window[0], window[1]);
+            assert!(matches!((i2 - i1, j2 - j1), (1, 0) | (0, 1) | (1, 1)));
+        }
+        let (warped_s, warped_q) = alignment.warp(&s, &q);
+        assert_eq!(warped_s.len(), alignment.path.len());
+        let cost = warped_s
+            .iter()
+            .zip(&warped_q)
+            .map(|(a, b)| (a - b).abs())
+            .sum::<i32>();
+        assert_eq!(cost, alignment.distance);
+
+        let s = array![[0.0, 0.0], [1.0, 1.0], [2.0, 0.0]];
+        let q = array![[0.0, 0.0], [2.0, 2.0], [2.0, 0.0], [2.0, 0.0]];
+        let alignment = dynamic_time_warp_vectors_alignment(s.view(), q.view(), Cosine);
+        assert_eq!(alignment.path, [(0, 0), (1, 1), (2, 2), (2, 3)]);
+        let (warped_s, warped_q) = alignment.warp_rows(s.view(), q.view());
+        assert_eq!(
+            warped_s,
+            array![[0.0, 0.0], [1.0, 1.0], [2.0, 0.0], [2.0, 0.0]]
+        );
+        assert_eq!(warped_q, q);
+
+        let empty = dynamic_time_warp_alignment(&[], &[1, 2]);
+        assert!(empty.path.is_empty());
+        assert_eq!(empty.distance, i32::MAX);
+        let empty = dynamic_time_warp_vectors_alignment(s.view(), q.slice(s![..0, ..]), Cosine);
+        assert!(empty.path.is_empty());
+        assert_eq!(empty.distance, f64::INFINITY);
+        let empty = dynamic_time_warp_alignment(&[], &[]);
+        assert!(empty.path.is_empty());
+        assert_eq!(empty.distance, 0);
+    }
+}
@@ -0,0 +1,35 @@
+use std::error::Error;
+use std::hash::Hash;
+
+use crate::huffman::{decode_symbols, encode_symbols, EncodedData, HuffmanEncoding, HuffmanError};
+
+// Anything that turns symbols into bits and back. The coder itself is the
+// model, so it has to be shared between the two sides out of band.
+pub(crate) trait EntropyCoder<S> {
+    type Error: Error;
+
+    fn encode(&self, symbols: &[S]) -> Result<EncodedData, Self::Error>;
+    fn decode(&self, encoded_data: &EncodedData) -> Result<Vec<S>, Self::Error>;
+
+    // Bits per symbol spent on `symbols`.
+    fn rate(&self, symbols: &[S]) -> Result<f64, Self::Error> {
+        let bits = self.encode(symbols)?.bit_len();
+        Ok(if symbols.is_empty() {
+            0.0
+        } else {
+            bits as f64 / symbols.len() as f64
+        })
+    }
+}
+
+impl<S: Hash + Eq + Copy> EntropyCoder<S> for HuffmanEncoding<S> {
+    type Error = HuffmanError;
+
+    fn encode(&self, symbols: &[S]) -> Result<EncodedData, HuffmanError> {
+        encode_symbols(self, symbols.iter().copied())
+    }
+
+    fn decode(&self, encoded_data: &EncodedData) -> Result<Vec<S>, HuffmanError> {
+        decode_symbols(self, encoded_data)
+    }
+}
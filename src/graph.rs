@@ -0,0 +1,41 @@
+use std::collections::{HashMap, HashSet};
+
+pub(crate) trait WeightedGraph {
+    fn nodes(&self) -> impl Iterator<Item = &str>;
+    fn neighbors(&self, node: &str) -> impl Iterator<Item = (&str, u64)>;
+
+    fn edges(&self) -> impl Iterator<Item = (&str, &str, u64)> {
+        self.nodes().flat_map(move |from| {
+            self.neighbors(from)
+                .map(move |(to, weight)| (from, to, weight))
+        })
+    }
+}
+
+pub(crate) fn sorted_nodes<G: WeightedGraph>(graph: &G) -> Vec<&str> {
+    let mut nodes = graph.nodes().collect::<HashSet<_>>();
+    nodes.extend(graph.edges().map(|(_, to, _)| to));
+    let mut nodes = nodes.into_iter().collect::<Vec<_>>();
+    nodes.sort_unstable();
+    nodes
+}
+
+// The graph types store directed edges, so a -> b and b -> a collapse into a
+// single undirected edge keeping the smaller weight. Self loops are dropped.
+pub(crate) fn undirected_edges<G: WeightedGraph>(graph: &G) -> Vec<(&str, &str, u64)> {
+    let mut edges: HashMap<(&str, &str), u64> = HashMap::new();
+    for (from, to, weight) in graph.edges() {
+        if from == to {
+            continue;
+        }
+        let key = if from < to { (from, to) } else { (to, from) };
+        let ref_ = edges.entry(key).or_insert(u64::MAX);
+        *ref_ = weight.min(*ref_);
+    }
+    let mut edges = edges
+        .into_iter()
+        .map(|((from, to), weight)| (from, to, weight))
+        .collect::<Vec<_>>();
+    edges.sort_by_key(|&(from, to, weight)| (weight, from, to));
+    edges
+}
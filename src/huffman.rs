@@ -1,5 +1,88 @@
 use std::collections::{BinaryHeap, HashMap};
-use std::fmt::Debug;
+use std::error::Error;
+use std::fmt::{Debug, Display, Formatter, Result as FmtResult};
+use std::hash::Hash;
+
+// Codes are stored in the `bits` of `Steps`, so this is the longest code that
+// can be represented.
+pub(crate) const MAX_CODE_LEN: u8 = u32::BITS as u8;
+
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub(crate) enum HuffmanError {
+    NoSymbols,
+    CodeTooLong { len: u8 },
+    InvalidMaxLength(u8),
+    TooManySymbols { symbols: usize, max_len: u8 },
+    // The symbol at `index` of the input has no code and there is no escape.
+    UnknownSymbol { index: usize },
+    InvalidCode,
+    // The data ends partway through a code.
+    TruncatedStream,
+    // The data ends with bits that cannot start any code.
+    TrailingBits { len: u8 },
+    InvalidEscape(u32),
+}
+
+impl Display for HuffmanError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> FmtResult {
+        match self {
+            HuffmanError::NoSymbols => write!(f, "there are no symbols to encode"),
+            HuffmanError::CodeTooLong { len } => write!(
+                f,
+                "a code of {len} bits does not fit in {MAX_CODE_LEN} bits"
+            ),
+            HuffmanError::InvalidMaxLength(max_len) => write!(
+                f,
+                "maximum code length {max_len} is not between 1 and {MAX_CODE_LEN}"
+            ),
+            HuffmanError::TooManySymbols { symbols, max_len } => write!(
+                f,
+                "{symbols} symbols cannot all have codes of at most {max_len} bits"
+            ),
+            HuffmanError::UnknownSymbol { index } => {
+                write!(f, "the symbol at index {index} has no code")
+            }
+            HuffmanError::InvalidCode => write!(f, "the data contains an invalid code"),
+            HuffmanError::TruncatedStream => write!(f, "the data ends partway through a code"),
+            HuffmanError::TrailingBits { len } => {
+                write!(f, "the data ends with {len} bits that are not a code")
+            }
+            HuffmanError::InvalidEscape(value) => {
+                write!(f, "escaped symbol {value:#x} is not a valid symbol")
+            }
+        }
+    }
+}
+
+impl Error for HuffmanError {}
+
+// Symbols that can be sent as a fixed number of raw bits, which is how symbols
+// without a code of their own are sent after an escape.
+pub(crate) trait RawSymbol: Hash + Eq + Copy {
+    const BITS: u8;
+    fn to_bits(self) -> u32;
+    fn from_bits(bits: u32) -> Option<Self>;
+}
+
+impl RawSymbol for u8 {
+    const BITS: u8 = 8;
+    fn to_bits(self) -> u32 {
+        self as u32
+    }
+    fn from_bits(bits: u32) -> Option<Self> {
+        u8::try_from(bits).ok()
+    }
+}
+
+impl RawSymbol for char {
+    const BITS: u8 = 21;
+    fn to_bits(self) -> u32 {
+        self as u32
+    }
+    fn from_bits(bits: u32) -> Option<Self> {
+        char::from_u32(bits)
+    }
+}
 
 #[repr(u8)]
 #[derive(Debug, PartialEq, Eq, Hash, Copy, Clone)]
@@ -102,26 +185,26 @@ impl Debug for Steps {
 }
 
 #[derive(Debug)]
-enum HuffmanNodeType {
-    Leaf(char),
-    Parent(Box<HuffmanNode>, Box<HuffmanNode>),
+enum HuffmanNodeType<S> {
+    Leaf(S),
+    Parent(Box<HuffmanNode<S>>, Box<HuffmanNode<S>>),
 }
 
 #[derive(Debug)]
-struct HuffmanNode {
+struct HuffmanNode<S> {
     frequency: u64,
-    kind: HuffmanNodeType,
+    kind: HuffmanNodeType<S>,
 }
 
 #[derive(Debug)]
-pub(crate) struct HuffmanTree {
-    root: HuffmanNode,
+pub(crate) struct HuffmanTree<S = char> {
+    root: HuffmanNode<S>,
 }
 
-impl HuffmanNode {
+impl<S: Copy> HuffmanNode<S> {
     fn visit<F>(&self, steps: &mut Steps, f: &mut F)
     where
-        F: FnMut(char, Steps),
+        F: FnMut(S, Steps),
     {
         match &self.kind {
             HuffmanNodeType::Leaf(value) => {
@@ -139,109 +222,550 @@ impl HuffmanNode {
     }
 }
 
-impl HuffmanTree {
+impl<S: Copy> HuffmanTree<S> {
     fn walk<F>(&self, mut f: F)
     where
-        F: FnMut(char, Steps),
+        F: FnMut(S, Steps),
     {
+        // A lone symbol still needs a code of one bit, or there would be
+        // nothing in the encoded data to count it by.
+        if let HuffmanNodeType::Leaf(value) = self.root.kind {
+            f(value, Steps { bits: 0, len: 1 });
+            return;
+        }
         let mut steps = Steps::new();
         self.root.visit(&mut steps, &mut f);
     }
+
+    pub(crate) fn max_code_len(&self) -> u8 {
+        let mut max_len = 0;
+        self.walk(|_, steps| max_len = max_len.max(steps.len));
+        max_len
+    }
+
+    // Sorted by symbol.
+    pub(crate) fn code_lengths(&self) -> Vec<(S, u8)>
+    where
+        S: Ord,
+    {
+        let mut lengths = Vec::new();
+        self.walk(|value, steps| lengths.push((value, steps.len)));
+        lengths.sort_unstable();
+        lengths
+    }
 }
 
-#[repr(transparent)]
-struct ComparableHuffmanNode(HuffmanNode);
+// Ties on frequency are broken by creation order, so building a tree from the
+// same symbols always gives the same shape.
+struct ComparableHuffmanNode<S>(HuffmanNode<S>, usize);
 
-impl PartialOrd for ComparableHuffmanNode {
+impl<S> PartialOrd for ComparableHuffmanNode<S> {
     fn partial_cmp(&self, other: &Self) -> Option<std::cmp::Ordering> {
         Some(self.cmp(other))
     }
 }
 
-impl Ord for ComparableHuffmanNode {
+impl<S> Ord for ComparableHuffmanNode<S> {
     fn cmp(&self, other: &Self) -> std::cmp::Ordering {
-        self.0.frequency.cmp(&other.0.frequency).reverse()
+        (self.0.frequency, self.1)
+            .cmp(&(other.0.frequency, other.1))
+            .reverse()
     }
 }
 
-impl PartialEq for ComparableHuffmanNode {
+impl<S> PartialEq for ComparableHuffmanNode<S> {
     fn eq(&self, other: &Self) -> bool {
-        self.0.frequency == other.0.frequency
+        self.cmp(other).is_eq()
     }
 }
 
-impl Eq for ComparableHuffmanNode {}
+impl<S> Eq for ComparableHuffmanNode<S> {}
+
+// A code for every symbol that has none of its own, followed by the raw bits
+// of the symbol.
+#[derive(Debug, Clone, Copy)]
+pub(crate) struct Escape<S> {
+    pub(crate) steps: Steps,
+    pub(crate) raw_bits: u8,
+    to_bits: fn(S) -> u32,
+    from_bits: fn(u32) -> Option<S>,
+}
+
+impl<S> Escape<S> {
+    pub(crate) fn raw(&self, symbol: S) -> u32 {
+        (self.to_bits)(symbol)
+    }
+
+    pub(crate) fn symbol(&self, bits: u32) -> Result<S, HuffmanError> {
+        (self.from_bits)(bits).ok_or(HuffmanError::InvalidEscape(bits))
+    }
+}
 
 #[derive(Debug)]
-pub(crate) struct HuffmanEncoding {
-    char_to_steps: HashMap<char, Steps>,
-    steps_to_char: HashMap<Steps, char>,
+pub(crate) struct HuffmanEncoding<S = char> {
+    char_to_steps: HashMap<S, Steps>,
+    steps_to_char: HashMap<Steps, S>,
+    escape: Option<Escape<S>>,
 }
 
-impl HuffmanEncoding {
-    pub(crate) fn new(tree: &HuffmanTree) -> Self {
+impl<S: Hash + Eq + Copy> HuffmanEncoding<S> {
+    // Panics if the tree is too deep for its codes to fit in `Steps`, see
+    // `try_new`.
+    pub(crate) fn new(tree: &HuffmanTree<S>) -> Self {
+        Self::try_new(tree).unwrap()
+    }
+
+    pub(crate) fn try_new(tree: &HuffmanTree<S>) -> Result<Self, HuffmanError> {
+        let len = tree.max_code_len();
+        if len > MAX_CODE_LEN {
+            return Err(HuffmanError::CodeTooLong { len });
+        }
         let mut char_to_steps = HashMap::new();
         let mut steps_to_char = HashMap::new();
         tree.walk(|value, steps| {
             char_to_steps.insert(value, steps);
             steps_to_char.insert(steps, value);
         });
+        Ok(Self {
+            char_to_steps,
+            steps_to_char,
+            escape: None,
+        })
+    }
+
+    // Builds codes for `tree`, whose `None` leaf becomes the escape.
+    pub(crate) fn try_with_escape(tree: &HuffmanTree<Option<S>>) -> Result<Self, HuffmanError>
+    where
+        S: RawSymbol,
+    {
+        let len = tree.max_code_len();
+        if len > MAX_CODE_LEN {
+            return Err(HuffmanError::CodeTooLong { len });
+        }
+        let mut codes = Vec::new();
+        let mut escape = None;
+        tree.walk(|value, steps| match value {
+            Some(value) => codes.push((value, steps)),
+            None => {
+                escape = Some(Escape {
+                    steps,
+                    raw_bits: S::BITS,
+                    to_bits: S::to_bits,
+                    from_bits: S::from_bits,
+                })
+            }
+        });
+        Ok(Self {
+            escape,
+            ..Self::from_steps(codes)
+        })
+    }
+
+    // Only the code lengths are taken from the tree, the codes themselves are
+    // assigned canonically, so equal lengths always give equal codes.
+    pub(crate) fn canonical(tree: &HuffmanTree<S>) -> Self
+    where
+        S: Ord,
+    {
+        Self::try_canonical(tree).unwrap()
+    }
+
+    pub(crate) fn try_canonical(tree: &HuffmanTree<S>) -> Result<Self, HuffmanError>
+    where
+        S: Ord,
+    {
+        let len = tree.max_code_len();
+        if len > MAX_CODE_LEN {
+            return Err(HuffmanError::CodeTooLong { len });
+        }
+        Ok(Self::from_code_lengths(tree.code_lengths()).unwrap())
+    }
+
+    fn from_steps<I>(codes: I) -> Self
+    where
+        I: IntoIterator<Item = (S, Steps)>,
+    {
+        let mut char_to_steps = HashMap::new();
+        let mut steps_to_char = HashMap::new();
+        for (value, steps) in codes {
+            char_to_steps.insert(value, steps);
+            steps_to_char.insert(steps, value);
+        }
         Self {
             char_to_steps,
             steps_to_char,
+            escape: None,
         }
     }
+
+    pub(crate) fn codes(&self) -> impl Iterator<Item = (S, Steps)> + '_ {
+        self.char_to_steps
+            .iter()
+            .map(|(symbol, steps)| (*symbol, *steps))
+    }
+
+    pub(crate) fn steps(&self, symbol: S) -> Option<Steps> {
+        self.char_to_steps.get(&symbol).copied()
+    }
+
+    pub(crate) fn escape(&self) -> Option<&Escape<S>> {
+        self.escape.as_ref()
+    }
+
+    pub(crate) fn max_code_len(&self) -> u8 {
+        self.codes()
+            .map(|(_, steps)| steps)
+            .chain(self.escape.map(|escape| escape.steps))
+            .map(|steps| steps.len)
+            .max()
+            .unwrap_or(0)
+    }
+
+    // Sorted by symbol.
+    pub(crate) fn code_lengths(&self) -> Vec<(S, u8)>
+    where
+        S: Ord,
+    {
+        let mut lengths = self
+            .char_to_steps
+            .iter()
+            .map(|(symbol, steps)| (*symbol, steps.len))
+            .collect::<Vec<_>>();
+        lengths.sort_unstable();
+        lengths
+    }
+
+    // Returns `None` if the lengths cannot form a prefix code, or any of them
+    // is zero.
+    pub(crate) fn from_code_lengths<I>(lengths: I) -> Option<Self>
+    where
+        S: Ord,
+        I: IntoIterator<Item = (S, u8)>,
+    {
+        canonical_codes(lengths).map(Self::from_steps)
+    }
+}
+
+// Assigns consecutive codes to symbols ordered by code length and then by
+// symbol, the same convention DEFLATE uses.
+fn canonical_codes<S, I>(lengths: I) -> Option<Vec<(S, Steps)>>
+where
+    S: Ord + Copy,
+    I: IntoIterator<Item = (S, u8)>,
+{
+    let mut lengths = lengths.into_iter().collect::<Vec<_>>();
+    lengths.sort_unstable_by_key(|&(symbol, len)| (len, symbol));
+    let mut codes = Vec::with_capacity(lengths.len());
+    let mut code = 0u64;
+    let mut previous_len = 0;
+    for (symbol, len) in lengths {
+        if len == 0 || len > MAX_CODE_LEN {
+            return None;
+        }
+        code <<= len - previous_len;
+        if code >> len != 0 {
+            return None;
+        }
+        codes.push((
+            symbol,
+            Steps {
+                bits: code as u32,
+                len,
+            },
+        ));
+        code += 1;
+        previous_len = len;
+    }
+    Some(codes)
 }
 
 #[derive(Debug)]
 pub(crate) struct EncodedData {
-    data: Vec<u8>,
-    last_byte_len: u8,
+    pub(crate) data: Vec<u8>,
+    pub(crate) last_byte_len: u8,
 }
 
-pub(crate) fn create_huffman_tree(text: &str) -> Option<HuffmanTree> {
-    let mut frequency_map = HashMap::new();
-    for c in text.chars() {
-        *frequency_map.entry(c).or_insert(0) += 1;
+impl EncodedData {
+    pub(crate) fn new() -> Self {
+        Self {
+            data: Vec::new(),
+            last_byte_len: 0,
+        }
+    }
+
+    pub(crate) fn push_bit(&mut self, bit: bool) {
+        if self.last_byte_len == 0 {
+            self.data.push(0);
+        }
+        let byte = self.data.last_mut().unwrap();
+        *byte = (*byte << 1) | bit as u8;
+        self.last_byte_len = (self.last_byte_len + 1) % 8;
+    }
+
+    // Most significant bit first, the order `encode` writes them in.
+    pub(crate) fn bits(&self) -> impl Iterator<Item = bool> + '_ {
+        let bit_len = self.bit_len();
+        (0..bit_len).map(move |index| {
+            let byte = self.data[(index / 8) as usize];
+            let byte_len = if index / 8 == bit_len / 8 {
+                self.last_byte_len as u64
+            } else {
+                8
+            };
+            byte & (1 << (byte_len - 1 - index % 8)) != 0
+        })
     }
-    let mut queue = frequency_map
+
+    pub(crate) fn bit_len(&self) -> u64 {
+        if self.last_byte_len == 0 {
+            self.data.len() as u64 * 8
+        } else {
+            (self.data.len() as u64 - 1) * 8 + self.last_byte_len as u64
+        }
+    }
+}
+
+// Frequencies are kept in first-seen order rather than `HashMap` order so
+// that everything built from them does not change from run to run.
+fn count_frequencies<S, I>(symbols: I) -> Vec<(S, u64)>
+where
+    S: Hash + Eq + Copy,
+    I: IntoIterator<Item = S>,
+{
+    let mut indices = HashMap::new();
+    let mut frequencies = Vec::new();
+    for symbol in symbols {
+        let index = *indices.entry(symbol).or_insert_with(|| {
+            frequencies.push((symbol, 0));
+            frequencies.len() - 1
+        });
+        frequencies[index].1 += 1;
+    }
+    frequencies
+}
+
+pub(crate) fn create_huffman_tree_from_symbols<S, I>(symbols: I) -> Option<HuffmanTree<S>>
+where
+    S: Hash + Eq + Copy,
+    I: IntoIterator<Item = S>,
+{
+    create_huffman_tree_from_frequencies(count_frequencies(symbols))
+}
+
+pub(crate) fn create_huffman_tree_from_frequencies<S, I>(frequencies: I) -> Option<HuffmanTree<S>>
+where
+    I: IntoIterator<Item = (S, u64)>,
+{
+    let frequencies = frequencies.into_iter().collect::<Vec<_>>();
+    let mut next_order = frequencies.len();
+    let mut queue = frequencies
         .into_iter()
-        .map(|(value, frequency)| {
-            ComparableHuffmanNode(HuffmanNode {
-                frequency,
-                kind: HuffmanNodeType::Leaf(value),
-            })
+        .enumerate()
+        .map(|(order, (value, frequency))| {
+            ComparableHuffmanNode(
+                HuffmanNode {
+                    frequency,
+                    kind: HuffmanNodeType::Leaf(value),
+                },
+                order,
+            )
         })
         .collect::<BinaryHeap<_>>();
     while queue.len() > 1 {
-        let ComparableHuffmanNode(left) = queue.pop().unwrap();
-        let ComparableHuffmanNode(right) = queue.pop().unwrap();
-        println!(
-            "left frequency: {}, right frequency: {}",
-            left.frequency, right.frequency
+        let ComparableHuffmanNode(left, _) = queue.pop().unwrap();
+        let ComparableHuffmanNode(right, _) = queue.pop().unwrap();
+        let new_node = ComparableHuffmanNode(
+            HuffmanNode {
+                frequency: left.frequency + right.frequency,
+                kind: HuffmanNodeType::Parent(Box::new(left), Box::new(right)),
+            },
+            next_order,
         );
-        let new_node = ComparableHuffmanNode(HuffmanNode {
-            frequency: left.frequency + right.frequency,
-            kind: HuffmanNodeType::Parent(Box::new(left), Box::new(right)),
-        });
+        next_order += 1;
         queue.push(new_node);
     }
     queue.pop().map(|v| HuffmanTree { root: v.0 })
 }
 
+pub(crate) fn create_huffman_tree(text: &str) -> Option<HuffmanTree> {
+    create_huffman_tree_from_symbols(text.chars())
+}
+
+pub(crate) fn create_byte_huffman_tree(data: &[u8]) -> Option<HuffmanTree<u8>> {
+    create_huffman_tree_from_symbols(data.iter().copied())
+}
+
 pub(crate) fn create_huffman_encoding(text: &str) -> Option<HuffmanEncoding> {
     create_huffman_tree(text).as_ref().map(HuffmanEncoding::new)
 }
 
-pub(crate) fn encode(encoding: &HuffmanEncoding, text: &str) -> EncodedData {
+pub(crate) fn create_byte_huffman_encoding(data: &[u8]) -> Option<HuffmanEncoding<u8>> {
+    create_byte_huffman_tree(data)
+        .as_ref()
+        .map(HuffmanEncoding::new)
+}
+
+pub(crate) fn create_canonical_huffman_encoding(text: &str) -> Option<HuffmanEncoding> {
+    create_huffman_tree(text)
+        .as_ref()
+        .map(HuffmanEncoding::canonical)
+}
+
+pub(crate) fn create_canonical_byte_huffman_encoding(data: &[u8]) -> Option<HuffmanEncoding<u8>> {
+    create_byte_huffman_tree(data)
+        .as_ref()
+        .map(HuffmanEncoding::canonical)
+}
+
+// Like `create_huffman_tree_from_symbols`, with an extra `None` leaf of
+// frequency one that becomes the escape for symbols that were not seen.
+pub(crate) fn create_huffman_encoding_with_escape_from_symbols<S, I>(
+    symbols: I,
+) -> Result<HuffmanEncoding<S>, HuffmanError>
+where
+    S: RawSymbol,
+    I: IntoIterator<Item = S>,
+{
+    let frequencies = count_frequencies(symbols)
+        .into_iter()
+        .map(|(symbol, frequency)| (Some(symbol), frequency))
+        .chain([(None, 1)]);
+    let tree = create_huffman_tree_from_frequencies(frequencies).unwrap();
+    HuffmanEncoding::try_with_escape(&tree)
+}
+
+pub(crate) fn create_huffman_encoding_with_escape(
+    text: &str,
+) -> Result<HuffmanEncoding, HuffmanError> {
+    create_huffman_encoding_with_escape_from_symbols(text.chars())
+}
+
+// Optimal code lengths subject to every code being at most `max_len` bits,
+// found with the package-merge algorithm of Larmore and Hirschberg. Sorted by
+// symbol.
+pub(crate) fn length_limited_code_lengths<S, I>(
+    frequencies: I,
+    max_len: u8,
+) -> Result<Vec<(S, u8)>, HuffmanError>
+where
+    S: Ord + Copy,
+    I: IntoIterator<Item = (S, u64)>,
+{
+    if max_len == 0 || max_len > MAX_CODE_LEN {
+        return Err(HuffmanError::InvalidMaxLength(max_len));
+    }
+    let mut leaves = frequencies.into_iter().collect::<Vec<_>>();
+    leaves.sort_unstable_by_key(|&(symbol, frequency)| (frequency, symbol));
+    let n = leaves.len();
+    if n == 0 {
+        return Err(HuffmanError::NoSymbols);
+    }
+    if n > 1 << max_len {
+        return Err(HuffmanError::TooManySymbols {
+            symbols: n,
+            max_len,
+        });
+    }
+
+    // Every list holds the leaves merged with the packages made by pairing up
+    // the previous list, one list per bit of code length. Only whether each
+    // item is a package needs to be remembered: packages are made from
+    // consecutive items, so the first k packages of a list always come from
+    // the first 2k items of the list before it.
+    let mut is_package = Vec::with_capacity(max_len as usize);
+    let mut previous = Vec::<u64>::new();
+    for _ in 0..max_len {
+        let mut packages = previous
+            .chunks_exact(2)
+            .map(|pair| pair[0] + pair[1])
+            .peekable();
+        let mut leaf_weights = leaves.iter().map(|&(_, frequency)| frequency).peekable();
+        let mut weights = Vec::with_capacity(n + previous.len() / 2);
+        let mut kinds = Vec::with_capacity(n + previous.len() / 2);
+        loop {
+            let take_leaf = match (leaf_weights.peek(), packages.peek()) {
+                (Some(leaf), Some(package)) => leaf <= package,
+                (Some(_), None) => true,
+                (None, Some(_)) => false,
+                (None, None) => break,
+            };
+            if take_leaf {
+                weights.push(leaf_weights.next().unwrap());
+            } else {
+                weights.push(packages.next().unwrap());
+            }
+            kinds.push(!take_leaf);
+        }
+        is_package.push(kinds);
+        previous = weights;
+    }
+
+    // A code of length l means the leaf is picked in l of the lists, starting
+    // from the 2n - 2 cheapest items of the last one.
+    // A lone symbol gets a one bit code, like it does in a tree.
+    let mut lengths = vec![0u8; n];
+    if n == 1 {
+        lengths[0] = 1;
+    }
+    let mut take = 2 * n - 2;
+    for kinds in is_package.iter().rev() {
+        let packages = kinds[..take].iter().filter(|&&kind| kind).count();
+        for len in lengths[..take - packages].iter_mut() {
+            *len += 1;
+        }
+        take = 2 * packages;
+    }
+
+    let mut lengths = leaves
+        .into_iter()
+        .map(|(symbol, _)| symbol)
+        .zip(lengths)
+        .collect::<Vec<_>>();
+    lengths.sort_unstable();
+    Ok(lengths)
+}
+
+pub(crate) fn create_length_limited_huffman_encoding_from_symbols<S, I>(
+    symbols: I,
+    max_len: u8,
+) -> Result<HuffmanEncoding<S>, HuffmanError>
+where
+    S: Hash + Ord + Copy,
+    I: IntoIterator<Item = S>,
+{
+    let lengths = length_limited_code_lengths(count_frequencies(symbols), max_len)?;
+    Ok(HuffmanEncoding::from_code_lengths(lengths).unwrap())
+}
+
+pub(crate) fn create_length_limited_huffman_encoding(
+    text: &str,
+    max_len: u8,
+) -> Result<HuffmanEncoding, HuffmanError> {
+    create_length_limited_huffman_encoding_from_symbols(text.chars(), max_len)
+}
+
+pub(crate) fn create_length_limited_byte_huffman_encoding(
+    data: &[u8],
+    max_len: u8,
+) -> Result<HuffmanEncoding<u8>, HuffmanError> {
+    create_length_limited_huffman_encoding_from_symbols(data.iter().copied(), max_len)
+}
+
+pub(crate) fn encode_symbols<S, I>(
+    encoding: &HuffmanEncoding<S>,
+    symbols: I,
+) -> Result<EncodedData, HuffmanError>
+where
+    S: Hash + Eq + Copy,
+    I: IntoIterator<Item = S>,
+{
     let mut result = Vec::new();
     let mut bits = 0;
     let mut len = 0;
-    for c in text.chars() {
-        let steps = encoding.char_to_steps.get(&c).unwrap();
-        for step in steps.into_iter().rev() {
+    let mut push_bits = |value: u32, value_len: u8| {
+        for shift in (0..value_len).rev() {
             bits <<= 1;
-            bits |= step as u32;
+            bits |= (value >> shift) & 1;
             len += 1;
             if len == 8 {
                 result.push(bits as u8);
@@ -249,47 +773,348 @@ pub(crate) fn encode(encoding: &HuffmanEncoding, text: &str) -> EncodedData {
                 len = 0;
             }
         }
+    };
+    for (index, c) in symbols.into_iter().enumerate() {
+        match (encoding.char_to_steps.get(&c), &encoding.escape) {
+            (Some(steps), _) => push_bits(steps.bits, steps.len),
+            (None, Some(escape)) => {
+                push_bits(escape.steps.bits, escape.steps.len);
+                push_bits(escape.raw(c), escape.raw_bits);
+            }
+            (None, None) => return Err(HuffmanError::UnknownSymbol { index }),
+        }
     }
     if len > 0 {
         result.push(bits as u8);
     }
-    EncodedData {
+    Ok(EncodedData {
         data: result,
         last_byte_len: len,
-    }
+    })
+}
+
+pub(crate) fn encode(encoding: &HuffmanEncoding, text: &str) -> Result<EncodedData, HuffmanError> {
+    encode_symbols(encoding, text.chars())
 }
 
-pub(crate) fn decode(encoding: &HuffmanEncoding, encoded_data: &EncodedData) -> String {
-    let mut result = String::new();
+pub(crate) fn encode_bytes(
+    encoding: &HuffmanEncoding<u8>,
+    data: &[u8],
+) -> Result<EncodedData, HuffmanError> {
+    encode_symbols(encoding, data.iter().copied())
+}
+
+pub(crate) fn decode_symbols<S: Hash + Eq + Copy>(
+    encoding: &HuffmanEncoding<S>,
+    encoded_data: &EncodedData,
+) -> Result<Vec<S>, HuffmanError> {
+    let max_len = encoding.max_code_len();
+    let escape = encoding.escape.map(|escape| escape.steps);
+    let mut result = Vec::new();
     let mut steps = Steps::new();
-    let data_len = encoded_data.data.len();
-    if data_len == 0 {
-        return String::new();
-    }
-    let full_bytes_len = if encoded_data.last_byte_len == 0 {
-        data_len
-    } else {
-        data_len - 1
-    };
-    macro_rules! process_step {
-        ($index:ident, $byte:ident, $offset:expr) => {
-            let step = Step::from($byte & (1 << ($offset - $index)) != 0);
-            steps.push(step);
-            if let Some(&c) = encoding.steps_to_char.get(&steps) {
-                result.push(c);
-                steps = Steps::new();
+    let mut bits = encoded_data.bits();
+    while let Some(bit) = bits.next() {
+        steps.push(Step::from(bit));
+        if let Some(&c) = encoding.steps_to_char.get(&steps) {
+            result.push(c);
+            steps = Steps::new();
+        } else if Some(steps) == escape {
+            let escape = encoding.escape.as_ref().unwrap();
+            let mut value = 0;
+            for _ in 0..escape.raw_bits {
+                let bit = bits.next().ok_or(HuffmanError::TruncatedStream)?;
+                value = (value << 1) | bit as u32;
             }
+            result.push(escape.symbol(value)?);
+            steps = Steps::new();
+        } else if steps.len >= max_len {
+            return Err(HuffmanError::InvalidCode);
+        }
+    }
+    if steps.len > 0 {
+        let is_prefix = encoding
+            .codes()
+            .map(|(_, code)| code)
+            .chain(escape)
+            .any(|code| code.len > steps.len && code.bits >> (code.len - steps.len) == steps.bits);
+        return Err(if is_prefix {
+            HuffmanError::TruncatedStream
+        } else {
+            HuffmanError::TrailingBits { len: steps.len }
+        });
+    }
+    Ok(result)
+}
+
+pub(crate) fn decode(
+    encoding: &HuffmanEncoding,
+    encoded_data: &EncodedData,
+) -> Result<String, HuffmanError> {
+    decode_symbols(encoding, encoded_data).map(|chars| chars.into_iter().collect())
+}
+
+pub(crate) fn decode_bytes(
+    encoding: &HuffmanEncoding<u8>,
+    encoded_data: &EncodedData,
+) -> Result<Vec<u8>, HuffmanError> {
+    decode_symbols(encoding, encoded_data)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn text_round_trip() {
+        let text = "This is test data for generating a huffman encoding!";
+        let encoding = create_huffman_encoding(text).unwrap();
+        let encoded = encode(&encoding, text).unwrap();
+        assert_eq!(decode(&encoding, &encoded).unwrap(), text);
+    }
+
+    #[test]
+    fn byte_round_trip() {
+        let data = (0..=255u8)
+            .chain((0..2000).map(|i| (i * i % 251) as u8))
+            .collect::<Vec<_>>();
+        let encoding = create_byte_huffman_encoding(&data).unwrap();
+        let encoded = encode_bytes(&encoding, &data).unwrap();
+        assert!(encoded.data.len() < data.len());
+        assert_eq!(decode_bytes(&encoding, &encoded).unwrap(), data);
+
+        let canonical = create_canonical_byte_huffman_encoding(&data).unwrap();
+        let canonical_encoded = encode_bytes(&canonical, &data).unwrap();
+        assert_eq!(canonical_encoded.bit_len(), encoded.bit_len());
+        assert_eq!(decode_bytes(&canonical, &canonical_encoded).unwrap(), data);
+
+        let limited = create_length_limited_byte_huffman_encoding(&data, 8).unwrap();
+        assert!(limited.code_lengths().iter().all(|&(_, len)| len == 8));
+        assert_eq!(
+            decode_bytes(&limited, &encode_bytes(&limited, &data).unwrap()).unwrap(),
+            data
+        );
+    }
+
+    #[test]
+    fn code_lengths_round_trip() {
+        let text = "abracadabra, alakazam";
+        let encoding = create_huffman_encoding(text).unwrap();
+        let lengths = encoding.code_lengths();
+        let canonical = HuffmanEncoding::from_code_lengths(lengths.clone()).unwrap();
+        assert_eq!(canonical.code_lengths(), lengths);
+        let encoded = encode(&canonical, text).unwrap();
+        assert_eq!(
+            encoded.bit_len(),
+            encode(&encoding, text).unwrap().bit_len()
+        );
+        assert_eq!(decode(&canonical, &encoded).unwrap(), text);
+        assert!(HuffmanEncoding::from_code_lengths([('a', 1), ('b', 1), ('c', 1)]).is_none());
+    }
+
+    #[test]
+    fn canonical_codes_match_deflate() {
+        // The example from RFC 1951, section 3.2.2.
+        let lengths = [
+            ('A', 3),
+            ('B', 3),
+            ('C', 3),
+            ('D', 3),
+            ('E', 3),
+            ('F', 2),
+            ('G', 4),
+            ('H', 4),
+        ];
+        let encoding = HuffmanEncoding::from_code_lengths(lengths).unwrap();
+        let codes = "ABCDEFGH"
+            .chars()
+            .map(|c| {
+                let steps = encoding.steps(c).unwrap();
+                format!("{:0width$b}", steps.bits, width = steps.len as usize)
+            })
+            .collect::<Vec<_>>();
+        assert_eq!(
+            codes,
+            ["010", "011", "100", "101", "110", "00", "1110", "1111"]
+        );
+    }
+
+    #[test]
+    fn canonical_encoding_is_deterministic() {
+        let text = "This is test data for generating a huffman encoding!";
+        let encoded = encode(&create_canonical_huffman_encoding(text).unwrap(), text).unwrap();
+        for _ in 0..10 {
+            let encoding = create_canonical_huffman_encoding(text).unwrap();
+            assert_eq!(encode(&encoding, text).unwrap().data, encoded.data);
+            let tree = create_huffman_tree(text).unwrap();
+            assert_eq!(tree.code_lengths(), encoding.code_lengths());
+        }
+        let encoding = create_canonical_huffman_encoding(text).unwrap();
+        assert_eq!(decode(&encoding, &encoded).unwrap(), text);
+    }
+
+    fn kraft_sum<S>(lengths: &[(S, u8)]) -> f64 {
+        lengths
+            .iter()
+            .map(|&(_, len)| 0.5f64.powi(len as i32))
+            .sum()
+    }
+
+    fn cost(frequencies: &[(u32, u64)], lengths: &[(u32, u8)]) -> u64 {
+        frequencies
+            .iter()
+            .zip(lengths)
+            .map(|(&(_, frequency), &(_, len))| frequency * len as u64)
+            .sum()
+    }
+
+    #[test]
+    fn length_limited_codes() {
+        let frequencies = (0..12u32).map(|i| (i, 1u64 << i)).collect::<Vec<_>>();
+        let tree = create_huffman_tree_from_frequencies(frequencies.clone()).unwrap();
+        assert_eq!(tree.max_code_len(), 11);
+        let unlimited = tree.code_lengths();
+
+        let lengths = length_limited_code_lengths(frequencies.clone(), 11).unwrap();
+        assert_eq!(cost(&frequencies, &lengths), cost(&frequencies, &unlimited));
+
+        for max_len in 4..11 {
+            let lengths = length_limited_code_lengths(frequencies.clone(), max_len).unwrap();
+            assert!(lengths.iter().all(|&(_, len)| (1..=max_len).contains(&len)));
+            assert_eq!(kraft_sum(&lengths), 1.0);
+            assert!(cost(&frequencies, &lengths) > cost(&frequencies, &unlimited));
+        }
+
+        assert_eq!(
+            length_limited_code_lengths(frequencies.clone(), 3),
+            Err(HuffmanError::TooManySymbols {
+                symbols: 12,
+                max_len: 3
+            })
+        );
+        assert_eq!(
+            length_limited_code_lengths(frequencies, 33),
+            Err(HuffmanError::InvalidMaxLength(33))
+        );
+        assert_eq!(
+            length_limited_code_lengths(Vec::<(u32, u64)>::new(), 15),
+            Err(HuffmanError::NoSymbols)
+        );
+
+        let text = "aaaaaaaaaaaaaaaabbbbbbbbccccdde";
+        let encoding = create_length_limited_huffman_encoding(text, 3).unwrap();
+        assert!(encoding.code_lengths().iter().all(|&(_, len)| len <= 3));
+        assert_eq!(
+            decode(&encoding, &encode(&encoding, text).unwrap()).unwrap(),
+            text
+        );
+    }
+
+    #[test]
+    fn rejects_codes_longer_than_steps() {
+        // Fibonacci frequencies give the deepest possible tree.
+        let mut frequencies = vec![(0u32, 1u64), (1, 1)];
+        for i in 2..40 {
+            frequencies.push((
+                i,
+                frequencies[i as usize - 1].1 + frequencies[i as usize - 2].1,
+            ));
+        }
+        let tree = create_huffman_tree_from_frequencies(frequencies.clone()).unwrap();
+        assert_eq!(tree.max_code_len(), 39);
+        assert_eq!(
+            HuffmanEncoding::try_new(&tree).unwrap_err(),
+            HuffmanError::CodeTooLong { len: 39 }
+        );
+        assert!(HuffmanEncoding::try_canonical(&tree).is_err());
+
+        let lengths = length_limited_code_lengths(frequencies, 15).unwrap();
+        assert!(lengths.iter().all(|&(_, len)| len <= 15));
+        assert_eq!(kraft_sum(&lengths), 1.0);
+        assert!(HuffmanEncoding::from_code_lengths(lengths).is_some());
+    }
+
+    #[test]
+    fn single_symbol() {
+        let text = "aaaa";
+        let encoding = create_huffman_encoding(text).unwrap();
+        assert_eq!(encoding.code_lengths(), [('a', 1)]);
+        let encoded = encode(&encoding, text).unwrap();
+        assert_eq!(encoded.bit_len(), 4);
+        assert_eq!(decode(&encoding, &encoded).unwrap(), text);
+
+        let canonical = create_canonical_huffman_encoding(text).unwrap();
+        assert_eq!(
+            decode(&canonical, &encode(&canonical, text).unwrap()).unwrap(),
+            text
+        );
+        let limited = create_length_limited_huffman_encoding(text, 15).unwrap();
+        assert_eq!(limited.code_lengths(), [('a', 1)]);
+    }
+
+    #[test]
+    fn reports_errors() {
+        let encoding = create_huffman_encoding("aabbbc").unwrap();
+        assert_eq!(
+            encode(&encoding, "abcd").unwrap_err(),
+            HuffmanError::UnknownSymbol { index: 3 }
+        );
+
+        // b is 0 and the other codes start with 1.
+        let truncated = EncodedData {
+            data: vec![0b01],
+            last_byte_len: 2,
+        };
+        assert_eq!(
+            decode(&encoding, &truncated),
+            Err(HuffmanError::TruncatedStream)
+        );
+
+        // Codes that do not cover every bit pattern can leave bits that are no
+        // code at all.
+        let encoding = HuffmanEncoding::from_code_lengths([('a', 1), ('b', 3)]).unwrap();
+        let trailing = EncodedData {
+            data: vec![0b011],
+            last_byte_len: 3,
         };
+        assert_eq!(
+            decode(&encoding, &trailing),
+            Err(HuffmanError::TrailingBits { len: 2 })
+        );
+        let invalid = EncodedData {
+            data: vec![0b1100_0000],
+            last_byte_len: 0,
+        };
+        assert_eq!(decode(&encoding, &invalid), Err(HuffmanError::InvalidCode));
     }
-    for byte in encoded_data.data[..full_bytes_len].iter().copied() {
-        for i in 0..8u8 {
-            process_step!(i, byte, 7);
+
+    #[test]
+    fn escapes_unseen_symbols() {
+        let encoding = create_huffman_encoding_with_escape("abracadabra").unwrap();
+        let text = "abracadabra, Ünïcödé!";
+        let encoded = encode(&encoding, text).unwrap();
+        assert_eq!(decode(&encoding, &encoded).unwrap(), text);
+        assert!(encoding.steps(',').is_none());
+
+        let escape = encoding.escape().unwrap().steps;
+        let mut invalid = EncodedData::new();
+        for step in escape.into_iter().rev() {
+            invalid.push_bit(step.into());
         }
+        for _ in 0..char::BITS {
+            invalid.push_bit(true);
+        }
+        assert_eq!(
+            decode(&encoding, &invalid),
+            Err(HuffmanError::InvalidEscape(0x1f_ffff))
+        );
     }
-    let byte = encoded_data.data[data_len - 1];
-    for i in 0..encoded_data.last_byte_len {
-        process_step!(i, byte, encoded_data.last_byte_len - 1);
+
+    #[test]
+    fn generic_symbol_round_trip() {
+        let symbols = [(0, 1), (2, 3), (0, 1), (0, 1), (4, 5), (2, 3)];
+        let tree = create_huffman_tree_from_symbols(symbols).unwrap();
+        let encoding = HuffmanEncoding::new(&tree);
+        let encoded = encode_symbols(&encoding, symbols).unwrap();
+        assert_eq!(decode_symbols(&encoding, &encoded).unwrap(), symbols);
     }
-    assert_eq!(steps, Steps::new());
-    result
 }
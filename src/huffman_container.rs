@@ -0,0 +1,267 @@
+use std::error::Error;
+use std::fmt::{Display, Formatter, Result as FmtResult};
+use std::hash::Hash;
+use std::io::{self, Read, Write};
+
+use crate::huffman::{decode_symbols, encode_symbols, EncodedData, HuffmanEncoding, HuffmanError};
+
+const MAGIC: &[u8; 4] = b"HUFF";
+const VERSION: u8 = 1;
+
+pub(crate) trait ContainerSymbol: Hash + Eq + Ord + Copy {
+    const KIND: u8;
+    fn to_u32(self) -> u32;
+    fn from_u32(value: u32) -> Option<Self>;
+}
+
+impl ContainerSymbol for u8 {
+    const KIND: u8 = 0;
+    fn to_u32(self) -> u32 {
+        self as u32
+    }
+    fn from_u32(value: u32) -> Option<Self> {
+        u8::try_from(value).ok()
+    }
+}
+
+impl ContainerSymbol for char {
+    const KIND: u8 = 1;
+    fn to_u32(self) -> u32 {
+        self as u32
+    }
+    fn from_u32(value: u32) -> Option<Self> {
+        char::from_u32(value)
+    }
+}
+
+#[derive(Debug)]
+pub(crate) enum ContainerError {
+    Io(io::Error),
+    BadMagic,
+    UnsupportedVersion(u8),
+    WrongSymbolKind { expected: u8, found: u8 },
+    InvalidSymbol(u32),
+    UnsortedSymbol(u32),
+    InvalidCodeLengths,
+    Huffman(HuffmanError),
+}
+
+impl Display for ContainerError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> FmtResult {
+        match self {
+            ContainerError::Io(error) => write!(f, "{error}"),
+            ContainerError::BadMagic => write!(f, "not a huffman container"),
+            ContainerError::UnsupportedVersion(version) => {
+                write!(f, "unsupported container version {version}")
+            }
+            ContainerError::WrongSymbolKind { expected, found } => {
+                write!(f, "expected symbol kind {expected}, found {found}")
+            }
+            ContainerError::InvalidSymbol(symbol) => write!(f, "invalid symbol {symbol:#x}"),
+            ContainerError::UnsortedSymbol(symbol) => {
+                write!(f, "symbol {symbol:#x} is out of order in the code table")
+            }
+            ContainerError::InvalidCodeLengths => {
+                write!(f, "code lengths do not form a prefix code")
+            }
+            ContainerError::Huffman(error) => write!(f, "{error}"),
+        }
+    }
+}
+
+impl Error for ContainerError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            ContainerError::Io(error) => Some(error),
+            ContainerError::Huffman(error) => Some(error),
+            _ => None,
+        }
+    }
+}
+
+impl From<io::Error> for ContainerError {
+    fn from(value: io::Error) -> Self {
+        ContainerError::Io(value)
+    }
+}
+
+impl From<HuffmanError> for ContainerError {
+    fn from(value: HuffmanError) -> Self {
+        ContainerError::Huffman(value)
+    }
+}
+
+// Layout, with all integers little endian:
+//
+//   magic          4 bytes, "HUFF"
+//   version        u8
+//   symbol kind    u8, 0 for bytes and 1 for chars
+//   symbol count   u32
+//   code table     symbol count entries of (symbol: u32, code length: u8),
+//                  sorted by symbol
+//   payload bits   u64
+//   payload        ceil(payload bits / 8) bytes
+//
+// Only the code lengths are stored, and the payload is encoded with the
+// canonical codes derived from them, so `encoding` does not need to be
+// canonical itself. Only symbols with codes can be written, the escape of an
+// encoding is not stored.
+pub(crate) fn write_to<S, I, W>(
+    encoding: &HuffmanEncoding<S>,
+    symbols: I,
+    mut writer: W,
+) -> Result<(), ContainerError>
+where
+    S: ContainerSymbol,
+    I: IntoIterator<Item = S>,
+    W: Write,
+{
+    let lengths = encoding.code_lengths();
+    let canonical = HuffmanEncoding::from_code_lengths(lengths.iter().copied()).unwrap();
+    let encoded = encode_symbols(&canonical, symbols)?;
+
+    writer.write_all(MAGIC)?;
+    writer.write_all(&[VERSION, S::KIND])?;
+    writer.write_all(&(lengths.len() as u32).to_le_bytes())?;
+    for (symbol, len) in lengths {
+        writer.write_all(&symbol.to_u32().to_le_bytes())?;
+        writer.write_all(&[len])?;
+    }
+    writer.write_all(&encoded.bit_len().to_le_bytes())?;
+    writer.write_all(&encoded.data)?;
+    writer.flush()?;
+    Ok(())
+}
+
+fn read_array<R: Read, const N: usize>(reader: &mut R) -> io::Result<[u8; N]> {
+    let mut buffer = [0; N];
+    reader.read_exact(&mut buffer)?;
+    Ok(buffer)
+}
+
+pub(crate) fn read_from<S, R>(mut reader: R) -> Result<Vec<S>, ContainerError>
+where
+    S: ContainerSymbol,
+    R: Read,
+{
+    if &read_array::<_, 4>(&mut reader)? != MAGIC {
+        return Err(ContainerError::BadMagic);
+    }
+    let [version, kind] = read_array(&mut reader)?;
+    if version != VERSION {
+        return Err(ContainerError::UnsupportedVersion(version));
+    }
+    if kind != S::KIND {
+        return Err(ContainerError::WrongSymbolKind {
+            expected: S::KIND,
+            found: kind,
+        });
+    }
+
+    let symbol_count = u32::from_le_bytes(read_array(&mut reader)?);
+    let mut lengths = Vec::new();
+    let mut previous = None;
+    for _ in 0..symbol_count {
+        let value = u32::from_le_bytes(read_array(&mut reader)?);
+        let [len] = read_array(&mut reader)?;
+        let symbol = S::from_u32(value).ok_or(ContainerError::InvalidSymbol(value))?;
+        if previous >= Some(symbol) {
+            return Err(ContainerError::UnsortedSymbol(value));
+        }
+        previous = Some(symbol);
+        lengths.push((symbol, len));
+    }
+    let encoding =
+        HuffmanEncoding::from_code_lengths(lengths).ok_or(ContainerError::InvalidCodeLengths)?;
+
+    let bit_len = u64::from_le_bytes(read_array(&mut reader)?);
+    let mut data = Vec::new();
+    reader.take(bit_len.div_ceil(8)).read_to_end(&mut data)?;
+    if (data.len() as u64) < bit_len.div_ceil(8) {
+        return Err(io::Error::from(io::ErrorKind::UnexpectedEof).into());
+    }
+    let encoded = EncodedData {
+        data,
+        last_byte_len: (bit_len % 8) as u8,
+    };
+    Ok(decode_symbols(&encoding, &encoded)?)
+}
+
+pub(crate) fn read_text_from<R: Read>(reader: R) -> Result<String, ContainerError> {
+    read_from::<char, _>(reader).map(|chars| chars.into_iter().collect())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::huffman::{create_byte_huffman_encoding, create_huffman_encoding};
+
+    #[test]
+    fn text_round_trip() {
+        let text = "This is test data for encoding and decoding! Ünïcödé too.";
+        let encoding = create_huffman_encoding(text).unwrap();
+        let mut file = Vec::new();
+        write_to(&encoding, text.chars(), &mut file).unwrap();
+        assert_eq!(&file[..6], b"HUFF\x01\x01");
+        let mut canonical_file = Vec::new();
+        let canonical = HuffmanEncoding::from_code_lengths(encoding.code_lengths()).unwrap();
+        write_to(&canonical, text.chars(), &mut canonical_file).unwrap();
+        assert_eq!(canonical_file, file);
+        assert_eq!(read_text_from(file.as_slice()).unwrap(), text);
+    }
+
+    #[test]
+    fn byte_round_trip() {
+        let data = (0..5000u32)
+            .map(|i| (i * 7 % 13) as u8 * 3)
+            .collect::<Vec<_>>();
+        let encoding = create_byte_huffman_encoding(&data).unwrap();
+        let mut file = Vec::new();
+        write_to(&encoding, data.iter().copied(), &mut file).unwrap();
+        assert!(file.len() < data.len() / 2);
+        assert_eq!(read_from::<u8, _>(file.as_slice()).unwrap(), data);
+    }
+
+    #[test]
+    fn rejects_bad_containers() {
+        let text = "abracadabra";
+        let encoding = create_huffman_encoding(text).unwrap();
+        let mut file = Vec::new();
+        write_to(&encoding, text.chars(), &mut file).unwrap();
+
+        assert!(matches!(
+            read_from::<u8, _>(file.as_slice()),
+            Err(ContainerError::WrongSymbolKind {
+                expected: 0,
+                found: 1
+            })
+        ));
+        assert!(matches!(
+            read_text_from(&file[..file.len() - 1]),
+            Err(ContainerError::Io(_))
+        ));
+
+        let mut bad_magic = file.clone();
+        bad_magic[0] = b'X';
+        assert!(matches!(
+            read_text_from(bad_magic.as_slice()),
+            Err(ContainerError::BadMagic)
+        ));
+        assert!(matches!(
+            write_to(&encoding, "abc!".chars(), Vec::new()),
+            Err(ContainerError::Huffman(HuffmanError::UnknownSymbol {
+                index: 3
+            }))
+        ));
+
+        // Every symbol claims a one bit code.
+        let mut bad_lengths = file.clone();
+        for entry in 0..5 {
+            bad_lengths[10 + entry * 5 + 4] = 1;
+        }
+        assert!(matches!(
+            read_text_from(bad_lengths.as_slice()),
+            Err(ContainerError::InvalidCodeLengths)
+        ));
+    }
+}
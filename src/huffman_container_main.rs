@@ -0,0 +1,27 @@
+use huffman::create_huffman_encoding;
+use huffman_container::{read_text_from, write_to};
+use std::fs::File;
+use std::io::{BufReader, BufWriter};
+
+mod huffman;
+mod huffman_container;
+
+fn main() {
+    let text = "This is test data for generating a huffman encoding!";
+    let path = std::env::temp_dir().join("huffman_container_main.huff");
+
+    let encoding = create_huffman_encoding(text).unwrap();
+    let file = BufWriter::new(File::create(&path).unwrap());
+    write_to(&encoding, text.chars(), file).unwrap();
+    println!(
+        "Wrote {} bytes for {} bytes of text to {}",
+        std::fs::metadata(&path).unwrap().len(),
+        text.len(),
+        path.display()
+    );
+
+    let file = BufReader::new(File::open(&path).unwrap());
+    let decoded_text = read_text_from(file).unwrap();
+    println!("{}", decoded_text);
+    assert_eq!(text, decoded_text);
+}
@@ -1,4 +1,7 @@
-use huffman::{create_huffman_tree, decode, encode, HuffmanEncoding};
+use huffman::{
+    create_huffman_encoding_with_escape, create_huffman_tree,
+    create_length_limited_huffman_encoding, decode, encode, HuffmanEncoding,
+};
 
 mod huffman;
 
@@ -9,9 +12,30 @@ fn main() {
     let huffman_encoding = HuffmanEncoding::new(&huffman_tree);
     println!("{:#?}", huffman_encoding);
     let text2 = "This is test data for encoding and decoding!";
-    let encoded_data = encode(&huffman_encoding, text2);
+    let encoded_data = encode(&huffman_encoding, text2).unwrap();
     println!("{:#?}", encoded_data);
-    let decoded_text = decode(&huffman_encoding, &encoded_data);
+    let decoded_text = decode(&huffman_encoding, &encoded_data).unwrap();
     println!("{}", decoded_text);
     assert_eq!(text2, decoded_text);
+
+    let canonical_encoding = HuffmanEncoding::canonical(&huffman_tree);
+    println!("{:?}", huffman_tree.code_lengths());
+    let encoded_data = encode(&canonical_encoding, text2).unwrap();
+    println!("{:#?}", encoded_data);
+    assert_eq!(text2, decode(&canonical_encoding, &encoded_data).unwrap());
+
+    let limited_encoding = create_length_limited_huffman_encoding(text, 5).unwrap();
+    println!("{:?}", limited_encoding.code_lengths());
+    let encoded_data = encode(&limited_encoding, text2).unwrap();
+    println!(
+        "{} bits with codes of at most 5 bits",
+        encoded_data.bit_len()
+    );
+    assert_eq!(text2, decode(&limited_encoding, &encoded_data).unwrap());
+
+    let text3 = "Text with symbols the encoding has never seen: Ünïcödé?";
+    println!("{}", encode(&huffman_encoding, text3).unwrap_err());
+    let escape_encoding = create_huffman_encoding_with_escape(text).unwrap();
+    let encoded_data = encode(&escape_encoding, text3).unwrap();
+    assert_eq!(text3, decode(&escape_encoding, &encoded_data).unwrap());
 }
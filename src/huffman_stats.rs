@@ -0,0 +1,275 @@
+use std::collections::HashMap;
+use std::fmt::{Debug, Display, Formatter, Result as FmtResult};
+use std::hash::Hash;
+
+use crate::huffman::{HuffmanEncoding, HuffmanError};
+
+#[derive(Debug, Clone, PartialEq)]
+pub(crate) struct SymbolStats<S> {
+    pub(crate) symbol: S,
+    pub(crate) count: u64,
+    pub(crate) probability: f64,
+    // Bits spent on every occurrence, including the raw bits after an escape.
+    pub(crate) bits: u32,
+    pub(crate) escaped: bool,
+}
+
+// All sizes are in bits, and everything per symbol is an average over the
+// symbols the statistics were taken from.
+#[derive(Debug, Clone, PartialEq)]
+pub(crate) struct CompressionStats<S> {
+    // Most frequent first, ties in order of first appearance.
+    pub(crate) symbols: Vec<SymbolStats<S>>,
+    pub(crate) total_symbols: u64,
+    // Shannon entropy of the symbols, the least any code can average.
+    pub(crate) entropy: f64,
+    // Also the cross-entropy between the symbols and the distribution the code
+    // lengths imply, 2^-len for every symbol.
+    pub(crate) average_code_len: f64,
+    pub(crate) original_bits: u64,
+    pub(crate) compressed_bits: u64,
+}
+
+impl<S> CompressionStats<S> {
+    // Bits per symbol lost to the code, against the best any code could do.
+    // When the encoding was built for different data this is the
+    // Kullback-Leibler divergence between the two.
+    pub(crate) fn redundancy(&self) -> f64 {
+        self.average_code_len - self.entropy
+    }
+
+    // Average code length over entropy, 1 for a perfect code.
+    pub(crate) fn efficiency(&self) -> f64 {
+        if self.average_code_len == 0.0 {
+            1.0
+        } else {
+            self.entropy / self.average_code_len
+        }
+    }
+
+    pub(crate) fn compression_ratio(&self) -> f64 {
+        if self.compressed_bits == 0 {
+            1.0
+        } else {
+            self.original_bits as f64 / self.compressed_bits as f64
+        }
+    }
+}
+
+impl<S: Debug> Display for CompressionStats<S> {
+    fn fmt(&self, f: &mut Formatter<'_>) -> FmtResult {
+        writeln!(
+            f,
+            "{:<12} {:>10} {:>12} {:>6}",
+            "symbol", "count", "probability", "bits"
+        )?;
+        for stats in self.symbols.iter() {
+            writeln!(
+                f,
+                "{:<12} {:>10} {:>12.6} {:>6}{}",
+                format!("{:?}", stats.symbol),
+                stats.count,
+                stats.probability,
+                stats.bits,
+                if stats.escaped { " (escaped)" } else { "" }
+            )?;
+        }
+        writeln!(f, "entropy:             {:.4} bits/symbol", self.entropy)?;
+        writeln!(
+            f,
+            "average code length: {:.4} bits/symbol",
+            self.average_code_len
+        )?;
+        writeln!(
+            f,
+            "redundancy:          {:.4} bits/symbol ({:.2}% efficient)",
+            self.redundancy(),
+            self.efficiency() * 100.0
+        )?;
+        write!(
+            f,
+            "size:                {} bits -> {} bits ({:.3}:1)",
+            self.original_bits,
+            self.compressed_bits,
+            self.compression_ratio()
+        )
+    }
+}
+
+impl<S: Hash + Eq + Copy> HuffmanEncoding<S> {
+    // `original_bits` is the size the symbols take in memory. Fails like
+    // `encode_symbols` does if a symbol has neither a code nor an escape.
+    pub(crate) fn statistics<I>(&self, symbols: I) -> Result<CompressionStats<S>, HuffmanError>
+    where
+        I: IntoIterator<Item = S>,
+    {
+        // Kept in order of first appearance, along with where that was.
+        let mut indices = HashMap::new();
+        let mut frequencies = Vec::new();
+        for (index, symbol) in symbols.into_iter().enumerate() {
+            let entry = *indices.entry(symbol).or_insert_with(|| {
+                frequencies.push((symbol, 0u64, index));
+                frequencies.len() - 1
+            });
+            frequencies[entry].1 += 1;
+        }
+        // Checked before sorting so that the error names the first unknown
+        // symbol in the input, as `encode_symbols` would.
+        if self.escape().is_none() {
+            if let Some(&(_, _, index)) = frequencies
+                .iter()
+                .find(|&&(symbol, _, _)| self.steps(symbol).is_none())
+            {
+                return Err(HuffmanError::UnknownSymbol { index });
+            }
+        }
+        // Stable, so equal counts stay in order of first appearance.
+        frequencies.sort_by(|(_, a, _), (_, b, _)| b.cmp(a));
+        let total_symbols = frequencies.iter().map(|&(_, count, _)| count).sum::<u64>();
+
+        let mut entropy = 0.0;
+        let mut compressed_bits = 0;
+        let mut stats = Vec::with_capacity(frequencies.len());
+        for (symbol, count, _) in frequencies {
+            let (bits, escaped) = match (self.steps(symbol), self.escape()) {
+                (Some(steps), _) => (steps.len as u32, false),
+                (None, Some(escape)) => (escape.steps.len as u32 + escape.raw_bits as u32, true),
+                (None, None) => unreachable!("unknown symbols are rejected above"),
+            };
+            let probability = count as f64 / total_symbols as f64;
+            entropy -= probability * probability.log2();
+            compressed_bits += count * bits as u64;
+            stats.push(SymbolStats {
+                symbol,
+                count,
+                probability,
+                bits,
+                escaped,
+            });
+        }
+
+        Ok(CompressionStats {
+            symbols: stats,
+            total_symbols,
+            entropy,
+            average_code_len: if total_symbols == 0 {
+                0.0
+            } else {
+                compressed_bits as f64 / total_symbols as f64
+            },
+            original_bits: total_symbols * (std::mem::size_of::<S>() * 8) as u64,
+            compressed_bits,
+        })
+    }
+
+    // Average bits per symbol this encoding spends on `symbols`, which need
+    // not be the symbols it was built from.
+    pub(crate) fn cross_entropy<I>(&self, symbols: I) -> Result<f64, HuffmanError>
+    where
+        I: IntoIterator<Item = S>,
+    {
+        self.statistics(symbols).map(|stats| stats.average_code_len)
+    }
+}
+
+impl HuffmanEncoding {
+    // Like `statistics`, with the original size taken as UTF-8.
+    pub(crate) fn text_statistics(
+        &self,
+        text: &str,
+    ) -> Result<CompressionStats<char>, HuffmanError> {
+        let mut stats = self.statistics(text.chars())?;
+        stats.original_bits = text.len() as u64 * 8;
+        Ok(stats)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::huffman::{
+        create_huffman_encoding, create_huffman_encoding_with_escape, encode, HuffmanEncoding,
+    };
+
+    fn assert_close(a: f64, b: f64) {
+        assert!((a - b).abs() < 1e-9, "{a} != {b}");
+    }
+
+    #[test]
+    fn dyadic_source_has_no_redundancy() {
+        // Probabilities of 1/2, 1/4, 1/8 and 1/8 are matched exactly by code
+        // lengths of 1, 2, 3 and 3.
+        let text = "aaaabbcd";
+        let encoding = create_huffman_encoding(text).unwrap();
+        let stats = encoding.text_statistics(text).unwrap();
+        assert_eq!(
+            stats
+                .symbols
+                .iter()
+                .map(|stats| (stats.symbol, stats.count, stats.bits))
+                .collect::<Vec<_>>(),
+            [('a', 4, 1), ('b', 2, 2), ('c', 1, 3), ('d', 1, 3)]
+        );
+        assert_eq!(stats.total_symbols, 8);
+        assert_close(stats.entropy, 1.75);
+        assert_close(stats.average_code_len, 1.75);
+        assert_close(stats.redundancy(), 0.0);
+        assert_eq!(stats.original_bits, 64);
+        assert_eq!(stats.compressed_bits, 14);
+        assert_eq!(
+            stats.compressed_bits,
+            encode(&encoding, text).unwrap().bit_len()
+        );
+        assert_close(stats.compression_ratio(), 64.0 / 14.0);
+        assert!(stats.to_string().contains("entropy:             1.7500"));
+    }
+
+    #[test]
+    fn skewed_source_has_redundancy() {
+        let text = "aaaaaaaaab";
+        let stats = create_huffman_encoding(text)
+            .unwrap()
+            .text_statistics(text)
+            .unwrap();
+        let entropy = -(0.9 * 0.9f64.log2() + 0.1 * 0.1f64.log2());
+        assert_close(stats.entropy, entropy);
+        assert_close(stats.average_code_len, 1.0);
+        assert_close(stats.redundancy(), 1.0 - entropy);
+        assert!(stats.efficiency() < 1.0);
+    }
+
+    #[test]
+    fn cross_entropy_against_other_text() {
+        let encoding = HuffmanEncoding::from_code_lengths([('a', 1), ('b', 2), ('c', 2)]).unwrap();
+        assert_close(encoding.cross_entropy("abcabc".chars()).unwrap(), 5.0 / 3.0);
+        assert_close(encoding.cross_entropy("aaaa".chars()).unwrap(), 1.0);
+        assert_eq!(
+            encoding.cross_entropy("abcd".chars()),
+            Err(HuffmanError::UnknownSymbol { index: 3 })
+        );
+        // The more frequent unknown symbol comes later.
+        let encoding = create_huffman_encoding("ab").unwrap();
+        assert_eq!(
+            encoding.statistics("aybzz".chars()).unwrap_err(),
+            encode(&encoding, "aybzz").unwrap_err()
+        );
+        assert_eq!(
+            encoding.statistics("aybzz".chars()).unwrap_err(),
+            HuffmanError::UnknownSymbol { index: 1 }
+        );
+
+        let encoding = create_huffman_encoding_with_escape("aab").unwrap();
+        let stats = encoding.text_statistics("abz").unwrap();
+        let escaped = stats.symbols.iter().find(|stats| stats.escaped).unwrap();
+        assert_eq!(escaped.symbol, 'z');
+        assert_eq!(
+            escaped.bits,
+            encoding.escape().unwrap().steps.len as u32 + 21
+        );
+        assert_eq!(
+            stats.compressed_bits,
+            encode(&encoding, "abz").unwrap().bit_len()
+        );
+        assert!(stats.redundancy() > 0.0);
+    }
+}
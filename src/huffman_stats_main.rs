@@ -0,0 +1,22 @@
+use huffman::create_huffman_encoding;
+
+mod huffman;
+mod huffman_stats;
+
+fn main() {
+    let text = "This is test data for generating a huffman encoding!";
+    let encoding = create_huffman_encoding(text).unwrap();
+    println!("{}", encoding.text_statistics(text).unwrap());
+
+    let text2 = "This is test data for encoding and decoding!";
+    println!();
+    println!("{}", encoding.text_statistics(text2).unwrap());
+
+    let encoding2 = create_huffman_encoding(text2).unwrap();
+    println!();
+    println!(
+        "{text2:?}: {:.4} bits/symbol with its own encoding, {:.4} with the first",
+        encoding2.cross_entropy(text2.chars()).unwrap(),
+        encoding.cross_entropy(text2.chars()).unwrap()
+    );
+}
@@ -0,0 +1,465 @@
+use std::fs::File;
+use std::io::{self, Read, Seek, SeekFrom, Write};
+use std::path::Path;
+
+use crate::huffman::{length_limited_code_lengths, HuffmanEncoding, HuffmanError, MAX_CODE_LEN};
+use crate::huffman_table::{BitBuffer, TableDecoder};
+
+const MAGIC: &[u8; 4] = b"HUFS";
+const VERSION: u8 = 1;
+const CHUNK_SIZE: usize = 8 * 1024;
+
+// Layout:
+//
+//   magic          4 bytes, "HUFS"
+//   version        u8
+//   code lengths   256 bytes, the code length of every byte value, or 0 if it
+//                  does not occur
+//   payload        the encoded bytes, most significant bit first
+//   trailer        u8, how many bits of the last payload byte are used, or 0
+//                  if the payload is empty
+//
+// The total length is not known until the end, so unlike the container format
+// it is only recorded at the end, in the trailer.
+pub(crate) struct HuffmanWriter<W: Write> {
+    inner: W,
+    encoding: HuffmanEncoding<u8>,
+    bits: BitBuffer,
+    output: Vec<u8>,
+    wrote_payload: bool,
+}
+
+impl<W: Write> HuffmanWriter<W> {
+    // The data is encoded with the canonical codes for the lengths in
+    // `encoding`, which are all the reader gets to see.
+    pub(crate) fn new(mut inner: W, encoding: &HuffmanEncoding<u8>) -> io::Result<Self> {
+        let lengths = encoding.code_lengths();
+        let mut header = [0u8; 256];
+        for &(symbol, len) in lengths.iter() {
+            header[symbol as usize] = len;
+        }
+        inner.write_all(MAGIC)?;
+        inner.write_all(&[VERSION])?;
+        inner.write_all(&header)?;
+        Ok(Self {
+            inner,
+            encoding: HuffmanEncoding::from_code_lengths(lengths).unwrap(),
+            bits: BitBuffer::new(),
+            output: Vec::with_capacity(CHUNK_SIZE),
+            wrote_payload: false,
+        })
+    }
+
+    // Drops bytes from `output` as they are written, so a failed flush can be
+    // retried without writing anything twice.
+    fn flush_output(&mut self) -> io::Result<()> {
+        while !self.output.is_empty() {
+            match self.inner.write(&self.output) {
+                Ok(0) => return Err(io::Error::from(io::ErrorKind::WriteZero)),
+                Ok(len) => {
+                    self.output.drain(..len);
+                }
+                Err(error) if error.kind() == io::ErrorKind::Interrupted => {}
+                Err(error) => return Err(error),
+            }
+        }
+        Ok(())
+    }
+
+    fn write_byte(&mut self, byte: u8) -> io::Result<()> {
+        if self.output.len() >= CHUNK_SIZE {
+            self.flush_output()?;
+        }
+        let steps = self.encoding.steps(byte).ok_or_else(|| {
+            io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!("byte {byte:#04x} has no code"),
+            )
+        })?;
+        self.bits.push(steps.bits as u64, steps.len);
+        while self.bits.len() >= 8 {
+            self.output.push(self.bits.peek(8) as u8);
+            self.bits.consume(8);
+            self.wrote_payload = true;
+        }
+        Ok(())
+    }
+
+    // Writes out the last partial byte and the trailer. Dropping the writer
+    // without calling this loses the end of the data.
+    pub(crate) fn finish(mut self) -> io::Result<W> {
+        let trailer = match self.bits.len() {
+            0 if self.wrote_payload => 8,
+            0 => 0,
+            len => {
+                self.output.push((self.bits.peek(8)) as u8);
+                len
+            }
+        };
+        self.output.push(trailer);
+        self.flush_output()?;
+        self.inner.flush()?;
+        Ok(self.inner)
+    }
+}
+
+impl<W: Write> Write for HuffmanWriter<W> {
+    // Once some of `buf` has been accepted, an error ends the write early and
+    // is left for the next call to run into.
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        for (consumed, &byte) in buf.iter().enumerate() {
+            match self.write_byte(byte) {
+                Ok(()) => {}
+                Err(error) if consumed == 0 => return Err(error),
+                Err(_) => return Ok(consumed),
+            }
+        }
+        Ok(buf.len())
+    }
+
+    // Only flushes whole bytes, the bits of a partial byte stay buffered until
+    // `finish`.
+    fn flush(&mut self) -> io::Result<()> {
+        self.flush_output()?;
+        self.inner.flush()
+    }
+}
+
+fn invalid_data(message: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, message)
+}
+
+pub(crate) struct HuffmanReader<R: Read> {
+    inner: R,
+    decoder: TableDecoder<u8>,
+    bits: BitBuffer,
+    input: Box<[u8]>,
+    input_pos: usize,
+    input_len: usize,
+    // The most recently read byte, held back because it is the trailer if the
+    // input ends right after it.
+    held_back: Option<u8>,
+    read_payload: bool,
+    at_end: bool,
+    // Hit after some bytes were already decoded, and returned by the next call
+    // to `read` instead.
+    pending_error: Option<io::Error>,
+}
+
+impl<R: Read> HuffmanReader<R> {
+    pub(crate) fn new(mut inner: R) -> io::Result<Self> {
+        let mut magic = [0; 4];
+        inner.read_exact(&mut magic)?;
+        if &magic != MAGIC {
+            return Err(invalid_data("not a huffman stream"));
+        }
+        let mut version = [0];
+        inner.read_exact(&mut version)?;
+        if version[0] != VERSION {
+            return Err(invalid_data("unsupported huffman stream version"));
+        }
+        let mut header = [0u8; 256];
+        inner.read_exact(&mut header)?;
+        let lengths = (0..=255u8)
+            .zip(header)
+            .filter(|&(_, len)| len > 0)
+            .collect::<Vec<_>>();
+        if lengths.iter().any(|&(_, len)| len > MAX_CODE_LEN) {
+            return Err(invalid_data("code lengths do not form a prefix code"));
+        }
+        let encoding = HuffmanEncoding::from_code_lengths(lengths)
+            .ok_or_else(|| invalid_data("code lengths do not form a prefix code"))?;
+        Ok(Self {
+            inner,
+            decoder: TableDecoder::new(&encoding),
+            bits: BitBuffer::new(),
+            input: vec![0; CHUNK_SIZE].into_boxed_slice(),
+            input_pos: 0,
+            input_len: 0,
+            held_back: None,
+            read_payload: false,
+            at_end: false,
+            pending_error: None,
+        })
+    }
+
+    fn next_byte(&mut self) -> io::Result<Option<u8>> {
+        if self.input_pos == self.input_len {
+            self.input_len = loop {
+                match self.inner.read(&mut self.input) {
+                    Err(error) if error.kind() == io::ErrorKind::Interrupted => continue,
+                    result => break result?,
+                }
+            };
+            self.input_pos = 0;
+            if self.input_len == 0 {
+                return Ok(None);
+            }
+        }
+        self.input_pos += 1;
+        Ok(Some(self.input[self.input_pos - 1]))
+    }
+
+    // Buffers at least 57 bits, or everything left of the payload.
+    fn refill(&mut self) -> io::Result<()> {
+        while !self.at_end && self.bits.len() <= 56 {
+            match self.next_byte()? {
+                Some(byte) => {
+                    if let Some(previous) = self.held_back.replace(byte) {
+                        self.bits.push(previous as u64, 8);
+                        self.read_payload = true;
+                    }
+                }
+                None => {
+                    self.at_end = true;
+                    let trailer = self
+                        .held_back
+                        .ok_or_else(|| io::Error::from(io::ErrorKind::UnexpectedEof))?;
+                    match (self.read_payload, trailer) {
+                        (false, 0) => {}
+                        (true, 1..=8) => self.bits.truncate_back(8 - trailer),
+                        _ => return Err(invalid_data("invalid huffman stream trailer")),
+                    }
+                }
+            }
+        }
+        Ok(())
+    }
+
+    fn read_byte(&mut self) -> io::Result<Option<u8>> {
+        self.refill()?;
+        if self.at_end && self.bits.is_empty() {
+            return Ok(None);
+        }
+        self.decoder
+            .decode_next(&mut self.bits)
+            .map(Some)
+            .map_err(|error| io::Error::new(io::ErrorKind::InvalidData, error))
+    }
+}
+
+impl<R: Read> Read for HuffmanReader<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        if let Some(error) = self.pending_error.take() {
+            return Err(error);
+        }
+        for (read, byte) in buf.iter_mut().enumerate() {
+            match self.read_byte() {
+                Ok(Some(value)) => *byte = value,
+                Ok(None) => return Ok(read),
+                Err(error) if read == 0 => return Err(error),
+                Err(error) => {
+                    self.pending_error = Some(error);
+                    return Ok(read);
+                }
+            }
+        }
+        Ok(buf.len())
+    }
+}
+
+pub(crate) fn count_byte_frequencies<R: Read>(mut reader: R) -> io::Result<[u64; 256]> {
+    let mut frequencies = [0u64; 256];
+    let mut chunk = vec![0; CHUNK_SIZE];
+    loop {
+        let len = match reader.read(&mut chunk) {
+            Ok(0) => return Ok(frequencies),
+            Ok(len) => len,
+            Err(error) if error.kind() == io::ErrorKind::Interrupted => continue,
+            Err(error) => return Err(error),
+        };
+        for &byte in &chunk[..len] {
+            frequencies[byte as usize] += 1;
+        }
+    }
+}
+
+pub(crate) fn encoding_from_byte_frequencies(frequencies: &[u64; 256]) -> HuffmanEncoding<u8> {
+    let frequencies = (0..=255u8)
+        .zip(frequencies.iter().copied())
+        .filter(|&(_, frequency)| frequency > 0);
+    // With at most 256 symbols, codes of up to `MAX_CODE_LEN` bits always
+    // exist.
+    let lengths = match length_limited_code_lengths(frequencies, MAX_CODE_LEN) {
+        Ok(lengths) => lengths,
+        Err(HuffmanError::NoSymbols) => Vec::new(),
+        Err(error) => unreachable!("{error}"),
+    };
+    HuffmanEncoding::from_code_lengths(lengths).unwrap()
+}
+
+// Reads the file twice, once to count byte frequencies and once to encode it,
+// so only a chunk of it is ever held in memory.
+pub(crate) fn compress_file<P: AsRef<Path>, W: Write>(path: P, writer: W) -> io::Result<W> {
+    let mut file = File::open(path)?;
+    let frequencies = count_byte_frequencies(&mut file)?;
+    let encoding = encoding_from_byte_frequencies(&frequencies);
+    file.seek(SeekFrom::Start(0))?;
+    let mut writer = HuffmanWriter::new(writer, &encoding)?;
+    io::copy(&mut file, &mut writer)?;
+    writer.finish()
+}
+
+// Returns the number of bytes written.
+pub(crate) fn decompress<R: Read, W: Write>(reader: R, mut writer: W) -> io::Result<u64> {
+    let mut reader = HuffmanReader::new(reader)?;
+    let len = io::copy(&mut reader, &mut writer)?;
+    writer.flush()?;
+    Ok(len)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Hands out data a few bytes at a time, like a slow pipe.
+    struct Trickle<'data>(&'data [u8]);
+
+    impl Read for Trickle<'_> {
+        fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+            let len = buf.len().min(self.0.len()).min(3);
+            buf[..len].copy_from_slice(&self.0[..len]);
+            self.0 = &self.0[len..];
+            Ok(len)
+        }
+    }
+
+    fn round_trip(data: &[u8]) -> Vec<u8> {
+        let encoding = encoding_from_byte_frequencies(&count_byte_frequencies(data).unwrap());
+        let mut writer = HuffmanWriter::new(Vec::new(), &encoding).unwrap();
+        for chunk in data.chunks(1000) {
+            writer.write_all(chunk).unwrap();
+        }
+        let compressed = writer.finish().unwrap();
+
+        let mut decompressed = Vec::new();
+        decompress(Trickle(&compressed), &mut decompressed).unwrap();
+        assert_eq!(decompressed, data);
+        compressed
+    }
+
+    #[test]
+    fn round_trips() {
+        let log = (0..2000)
+            .map(|i| {
+                format!(
+                    "2024-01-01T00:00:{:02} INFO request {i} took {}ms\n",
+                    i % 60,
+                    i * 7 % 300
+                )
+            })
+            .collect::<String>();
+        let compressed = round_trip(log.as_bytes());
+        assert!(compressed.len() < log.len() * 3 / 4);
+
+        round_trip(b"");
+        round_trip(b"aaaaaaaa");
+        round_trip(b"aaaaaaab");
+        round_trip(&(0..=255u8).cycle().take(10_000).collect::<Vec<_>>());
+    }
+
+    #[test]
+    fn compresses_files() {
+        let path = std::env::temp_dir().join(format!("huffman_stream_test_{}", std::process::id()));
+        let data = "the quick brown fox jumps over the lazy dog\n".repeat(500);
+        std::fs::write(&path, &data).unwrap();
+        let compressed = compress_file(&path, Vec::new()).unwrap();
+        std::fs::remove_file(&path).unwrap();
+        let mut decompressed = Vec::new();
+        assert_eq!(
+            decompress(compressed.as_slice(), &mut decompressed).unwrap(),
+            data.len() as u64
+        );
+        assert_eq!(decompressed, data.as_bytes());
+    }
+
+    #[test]
+    fn rejects_bad_streams() {
+        let encoding =
+            encoding_from_byte_frequencies(&count_byte_frequencies(&b"abc"[..]).unwrap());
+        let mut writer = HuffmanWriter::new(Vec::new(), &encoding).unwrap();
+        assert_eq!(
+            writer.write_all(b"abd").unwrap_err().kind(),
+            io::ErrorKind::InvalidInput
+        );
+
+        let mut writer = HuffmanWriter::new(Vec::new(), &encoding).unwrap();
+        writer.write_all(b"abcabc").unwrap();
+        let compressed = writer.finish().unwrap();
+        let mut decompressed = Vec::new();
+
+        let error = decompress(&compressed[..compressed.len() - 1], &mut decompressed).unwrap_err();
+        assert_eq!(error.kind(), io::ErrorKind::InvalidData);
+
+        let mut bad_magic = compressed.clone();
+        bad_magic[0] = b'X';
+        let error = decompress(bad_magic.as_slice(), &mut decompressed).unwrap_err();
+        assert_eq!(error.kind(), io::ErrorKind::InvalidData);
+
+        let error = decompress(&compressed[..100], &mut decompressed).unwrap_err();
+        assert_eq!(error.kind(), io::ErrorKind::UnexpectedEof);
+    }
+
+    // Accepts `limit` bytes and then fails every write.
+    struct Full {
+        written: Vec<u8>,
+        limit: usize,
+    }
+
+    impl Write for Full {
+        fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+            let len = buf.len().min(self.limit - self.written.len());
+            if len == 0 {
+                return Err(io::Error::other("full"));
+            }
+            self.written.extend_from_slice(&buf[..len]);
+            Ok(len)
+        }
+
+        fn flush(&mut self) -> io::Result<()> {
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn reports_partial_progress() {
+        let encoding =
+            encoding_from_byte_frequencies(&count_byte_frequencies(&b"abc"[..]).unwrap());
+        let mut writer = HuffmanWriter::new(Vec::new(), &encoding).unwrap();
+        assert_eq!(writer.write(b"abd").unwrap(), 2);
+        assert_eq!(
+            writer.write(b"d").unwrap_err().kind(),
+            io::ErrorKind::InvalidInput
+        );
+
+        let inner = Full {
+            written: Vec::new(),
+            limit: 4 + 1 + 256 + 100,
+        };
+        let mut writer = HuffmanWriter::new(inner, &encoding).unwrap();
+        let data = b"abc".repeat(CHUNK_SIZE * 4);
+        let written = writer.write(&data).unwrap();
+        assert!(0 < written && written < data.len());
+        assert_eq!(
+            writer.write_all(&data[written..]).unwrap_err().to_string(),
+            "full"
+        );
+
+        // Only `a` has a code, so the one bits after the first two symbols
+        // cannot be decoded.
+        let mut stream = MAGIC.to_vec();
+        stream.push(VERSION);
+        let mut header = [0u8; 256];
+        header[b'a' as usize] = 1;
+        stream.extend_from_slice(&header);
+        stream.extend_from_slice(&[0b0011_1111, 8]);
+        let mut reader = HuffmanReader::new(stream.as_slice()).unwrap();
+        let mut buf = [0; 8];
+        assert_eq!(reader.read(&mut buf).unwrap(), 2);
+        assert_eq!(&buf[..2], b"aa");
+        assert_eq!(
+            reader.read(&mut buf).unwrap_err().kind(),
+            io::ErrorKind::InvalidData
+        );
+    }
+}
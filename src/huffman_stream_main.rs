@@ -0,0 +1,37 @@
+use huffman_stream::{compress_file, decompress};
+use std::fs::{self, File};
+use std::io::{BufReader, BufWriter, Write};
+
+mod huffman;
+mod huffman_stream;
+mod huffman_table;
+
+fn main() {
+    let directory = std::env::temp_dir();
+    let log_path = directory.join("huffman_stream_main.log");
+    let compressed_path = directory.join("huffman_stream_main.log.huff");
+
+    let mut log = BufWriter::new(File::create(&log_path).unwrap());
+    for i in 0..100_000 {
+        let level = if i % 17 == 0 { "WARN" } else { "INFO" };
+        writeln!(log, "{level} request {i} finished in {}ms", i * 37 % 1000).unwrap();
+    }
+    log.into_inner().unwrap();
+
+    let compressed = BufWriter::new(File::create(&compressed_path).unwrap());
+    compress_file(&log_path, compressed)
+        .unwrap()
+        .into_inner()
+        .unwrap();
+    println!(
+        "{} bytes compressed to {} bytes",
+        fs::metadata(&log_path).unwrap().len(),
+        fs::metadata(&compressed_path).unwrap().len()
+    );
+
+    let mut decompressed = Vec::new();
+    let compressed = BufReader::new(File::open(&compressed_path).unwrap());
+    let len = decompress(compressed, &mut decompressed).unwrap();
+    println!("{len} bytes decompressed");
+    assert_eq!(decompressed, fs::read(&log_path).unwrap());
+}
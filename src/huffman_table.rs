@@ -0,0 +1,335 @@
+use std::collections::BTreeMap;
+use std::hash::Hash;
+
+use crate::huffman::{EncodedData, Escape, HuffmanEncoding, HuffmanError};
+
+// Bits looked up at once, both by the primary table and by every subtable.
+// Codes no longer than this are decoded with a single lookup.
+const TABLE_BITS: u8 = 9;
+
+#[derive(Debug, Clone, Copy)]
+enum Entry<S> {
+    Invalid,
+    // `len` is the number of bits of this table's lookup the code uses.
+    Symbol { symbol: S, len: u8 },
+    Escape { len: u8 },
+    Subtable { start: usize, bits: u8 },
+}
+
+// Decodes several bits per step instead of probing `steps_to_char` after every
+// bit. The primary table is indexed by the next `root_bits` bits of input and
+// either gives a symbol directly or, for longer codes, points at a subtable
+// indexed by the bits that follow, as in zlib's inflate.
+#[derive(Debug)]
+pub(crate) struct TableDecoder<S = char> {
+    entries: Vec<Entry<S>>,
+    root_bits: u8,
+    escape: Option<Escape<S>>,
+}
+
+impl<S: Hash + Eq + Copy> TableDecoder<S> {
+    pub(crate) fn new(encoding: &HuffmanEncoding<S>) -> Self {
+        // The escape goes in the table as a `None` symbol.
+        let escape = encoding.escape().copied();
+        let codes = encoding
+            .codes()
+            .map(|(symbol, steps)| (Some(symbol), steps))
+            .chain(escape.map(|escape| (None, escape.steps)))
+            .map(|(symbol, steps)| (symbol, steps.bits as u64, steps.len))
+            .collect::<Vec<_>>();
+        let root_bits = encoding.max_code_len().clamp(1, TABLE_BITS);
+        let mut decoder = Self {
+            entries: Vec::new(),
+            root_bits,
+            escape,
+        };
+        decoder.build(codes, root_bits);
+        decoder
+    }
+
+    // `codes` hold the bits of every code that are left after the lookups of
+    // the tables above this one, and their lengths.
+    fn build(&mut self, codes: Vec<(Option<S>, u64, u8)>, bits: u8) -> usize {
+        let start = self.entries.len();
+        self.entries.resize(start + (1 << bits), Entry::Invalid);
+        let mut long_codes = BTreeMap::<u64, Vec<_>>::new();
+        for (symbol, code, len) in codes {
+            if len <= bits {
+                let first = (code << (bits - len)) as usize;
+                for entry in &mut self.entries[start + first..start + first + (1 << (bits - len))] {
+                    *entry = match symbol {
+                        Some(symbol) => Entry::Symbol { symbol, len },
+                        None => Entry::Escape { len },
+                    };
+                }
+            } else {
+                let rest = len - bits;
+                long_codes.entry(code >> rest).or_default().push((
+                    symbol,
+                    code & ((1 << rest) - 1),
+                    rest,
+                ));
+            }
+        }
+        for (prefix, codes) in long_codes {
+            let max_len = codes.iter().map(|&(_, _, len)| len).max().unwrap();
+            let sub_bits = max_len.min(TABLE_BITS);
+            let sub_start = self.build(codes, sub_bits);
+            self.entries[start + prefix as usize] = Entry::Subtable {
+                start: sub_start,
+                bits: sub_bits,
+            };
+        }
+        start
+    }
+
+    // Decodes the symbol at the front of `bits`. The caller has to make sure
+    // `bits` holds at least 57 bits, enough for the longest code or an escape
+    // and the raw bits that follow it, or everything that is left of the
+    // input.
+    pub(crate) fn decode_next(&self, bits: &mut BitBuffer) -> Result<S, HuffmanError> {
+        let mut start = 0;
+        let mut table_bits = self.root_bits;
+        loop {
+            match self.entries[start + bits.peek(table_bits) as usize] {
+                Entry::Symbol { symbol, len } => {
+                    bits.consume(len).ok_or(HuffmanError::TruncatedStream)?;
+                    return Ok(symbol);
+                }
+                Entry::Escape { len } => {
+                    bits.consume(len).ok_or(HuffmanError::TruncatedStream)?;
+                    let escape = self.escape.as_ref().unwrap();
+                    let value = bits.peek(escape.raw_bits) as u32;
+                    bits.consume(escape.raw_bits)
+                        .ok_or(HuffmanError::TruncatedStream)?;
+                    return escape.symbol(value);
+                }
+                Entry::Subtable {
+                    start: sub_start,
+                    bits: sub_bits,
+                } => {
+                    bits.consume(table_bits)
+                        .ok_or(HuffmanError::TruncatedStream)?;
+                    start = sub_start;
+                    table_bits = sub_bits;
+                }
+                Entry::Invalid => return Err(HuffmanError::InvalidCode),
+            }
+        }
+    }
+
+    pub(crate) fn decode_symbols(
+        &self,
+        encoded_data: &EncodedData,
+    ) -> Result<Vec<S>, HuffmanError> {
+        let mut result = Vec::new();
+        let mut reader = BitReader::new(encoded_data);
+        reader.refill();
+        while !reader.bits.is_empty() {
+            result.push(self.decode_next(&mut reader.bits)?);
+            reader.refill();
+        }
+        Ok(result)
+    }
+}
+
+impl TableDecoder<char> {
+    pub(crate) fn decode(&self, encoded_data: &EncodedData) -> Result<String, HuffmanError> {
+        self.decode_symbols(encoded_data)
+            .map(|chars| chars.into_iter().collect())
+    }
+}
+
+impl TableDecoder<u8> {
+    pub(crate) fn decode_bytes(&self, encoded_data: &EncodedData) -> Result<Vec<u8>, HuffmanError> {
+        self.decode_symbols(encoded_data)
+    }
+}
+
+// Up to 64 bits of input, oldest first, kept in the low bits of `buffer`.
+#[derive(Debug, Default)]
+pub(crate) struct BitBuffer {
+    buffer: u64,
+    len: u8,
+}
+
+impl BitBuffer {
+    pub(crate) fn new() -> Self {
+        Self::default()
+    }
+
+    pub(crate) fn len(&self) -> u8 {
+        self.len
+    }
+
+    pub(crate) fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    // Appends the low `len` bits of `bits`, most significant first.
+    pub(crate) fn push(&mut self, bits: u64, len: u8) {
+        debug_assert!(self.len + len <= 64);
+        if len > 0 {
+            self.buffer = (self.buffer << len) | (bits & (u64::MAX >> (64 - len)));
+            self.len += len;
+        }
+    }
+
+    // Missing bits past the end of the buffer read as zeros.
+    pub(crate) fn peek(&self, len: u8) -> u64 {
+        let mask = (1 << len) - 1;
+        if self.len >= len {
+            (self.buffer >> (self.len - len)) & mask
+        } else {
+            (self.buffer << (len - self.len)) & mask
+        }
+    }
+
+    // Returns `None`, leaving the buffer as it was, if there are fewer than
+    // `len` bits.
+    pub(crate) fn consume(&mut self, len: u8) -> Option<()> {
+        self.len = self.len.checked_sub(len)?;
+        Some(())
+    }
+
+    // Drops the `len` most recently pushed bits.
+    pub(crate) fn truncate_back(&mut self, len: u8) {
+        self.buffer = self.buffer.checked_shr(len as u32).unwrap_or(0);
+        self.len -= len;
+    }
+}
+
+// Feeds the bytes of `EncodedData` into a `BitBuffer`.
+struct BitReader<'data> {
+    data: &'data [u8],
+    last_byte_len: u8,
+    next_byte: usize,
+    bits: BitBuffer,
+}
+
+impl<'data> BitReader<'data> {
+    fn new(encoded_data: &'data EncodedData) -> Self {
+        Self {
+            data: &encoded_data.data,
+            last_byte_len: encoded_data.last_byte_len,
+            next_byte: 0,
+            bits: BitBuffer::new(),
+        }
+    }
+
+    fn refill(&mut self) {
+        while self.bits.len() <= 56 && self.next_byte < self.data.len() {
+            let byte = self.data[self.next_byte];
+            self.next_byte += 1;
+            let len = if self.next_byte == self.data.len() && self.last_byte_len != 0 {
+                self.last_byte_len
+            } else {
+                8
+            };
+            self.bits.push(byte as u64, len);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::huffman::{
+        create_byte_huffman_encoding, create_huffman_encoding, create_huffman_encoding_with_escape,
+        create_huffman_tree_from_frequencies, decode, decode_bytes, decode_symbols, encode,
+        encode_bytes, encode_symbols, length_limited_code_lengths,
+    };
+
+    #[test]
+    fn matches_decode() {
+        let text = "This is test data for encoding and decoding! Ünïcödé too. \
+                    Some rarer symbols: qQzZxX#@%&*~^"
+            .repeat(20);
+        let encoding = create_huffman_encoding(&text).unwrap();
+        let encoded = encode(&encoding, &text).unwrap();
+        let decoder = TableDecoder::new(&encoding);
+        assert_eq!(
+            decoder.decode(&encoded).unwrap(),
+            decode(&encoding, &encoded).unwrap()
+        );
+        assert_eq!(decoder.decode(&encoded).unwrap(), text);
+
+        let data = (0..10_000u32)
+            .map(|i| (i * i % 253) as u8)
+            .collect::<Vec<_>>();
+        let encoding = create_byte_huffman_encoding(&data).unwrap();
+        let encoded = encode_bytes(&encoding, &data).unwrap();
+        let decoder = TableDecoder::new(&encoding);
+        assert_eq!(
+            decoder.decode_bytes(&encoded).unwrap(),
+            decode_bytes(&encoding, &encoded).unwrap()
+        );
+    }
+
+    #[test]
+    fn long_codes_use_nested_subtables() {
+        // Fibonacci frequencies give codes of every length up to 24, which
+        // need two levels of subtables below the primary table.
+        let mut frequencies = vec![(0u32, 1u64), (1, 1)];
+        for i in 2..25 {
+            frequencies.push((
+                i,
+                frequencies[i as usize - 1].1 + frequencies[i as usize - 2].1,
+            ));
+        }
+        let tree = create_huffman_tree_from_frequencies(frequencies.clone()).unwrap();
+        assert_eq!(tree.max_code_len(), 24);
+        let encoding = HuffmanEncoding::new(&tree);
+        let symbols = (0..25)
+            .chain((0..25).rev())
+            .chain([0, 24, 1, 23])
+            .collect::<Vec<_>>();
+        let encoded = encode_symbols(&encoding, symbols.iter().copied()).unwrap();
+        let decoder = TableDecoder::new(&encoding);
+        assert_eq!(decoder.decode_symbols(&encoded).unwrap(), symbols);
+        assert_eq!(decode_symbols(&encoding, &encoded).unwrap(), symbols);
+
+        let lengths = length_limited_code_lengths(frequencies, 12).unwrap();
+        let encoding = HuffmanEncoding::from_code_lengths(lengths).unwrap();
+        let encoded = encode_symbols(&encoding, symbols.iter().copied()).unwrap();
+        assert_eq!(
+            TableDecoder::new(&encoding)
+                .decode_symbols(&encoded)
+                .unwrap(),
+            symbols
+        );
+    }
+
+    #[test]
+    fn escapes_and_errors() {
+        let encoding = create_huffman_encoding_with_escape("abracadabra").unwrap();
+        let text = "abracadabra, Ünïcödé!";
+        let encoded = encode(&encoding, text).unwrap();
+        let decoder = TableDecoder::new(&encoding);
+        assert_eq!(decoder.decode(&encoded).unwrap(), text);
+
+        let truncated = EncodedData {
+            data: encoded.data[..encoded.data.len() - 1].to_vec(),
+            last_byte_len: 0,
+        };
+        assert_eq!(
+            decoder.decode(&truncated),
+            Err(HuffmanError::TruncatedStream)
+        );
+
+        let encoding = HuffmanEncoding::from_code_lengths([('a', 1), ('b', 3)]).unwrap();
+        let invalid = EncodedData {
+            data: vec![0b1100_0000],
+            last_byte_len: 0,
+        };
+        let decoder = TableDecoder::new(&encoding);
+        assert_eq!(decoder.decode(&invalid), Err(HuffmanError::InvalidCode));
+    }
+
+    #[test]
+    fn empty_input() {
+        let encoding = create_huffman_encoding("ab").unwrap();
+        let encoded = encode(&encoding, "").unwrap();
+        assert_eq!(TableDecoder::new(&encoding).decode(&encoded).unwrap(), "");
+    }
+}
@@ -0,0 +1,74 @@
+use bench_util::{format_duration, XorShift};
+use huffman::{create_huffman_encoding, decode, encode};
+use huffman_table::TableDecoder;
+use std::time::Instant;
+
+mod bench_util;
+mod huffman;
+mod huffman_table;
+
+const TEXT_LEN: usize = 4_000_000;
+
+// Words drawn with a skewed distribution from a small vocabulary, so the text
+// has the kind of symbol frequencies real text has.
+fn random_text(len: usize) -> String {
+    const WORDS: [&str; 16] = [
+        "the",
+        "of",
+        "and",
+        "to",
+        "in",
+        "a",
+        "is",
+        "that",
+        "for",
+        "it",
+        "huffman",
+        "table",
+        "decoder",
+        "QUICKLY",
+        "zephyr",
+        "Ünïcödé",
+    ];
+    let mut rng = XorShift::default();
+    let mut text = String::with_capacity(len + 16);
+    while text.len() < len {
+        let state = rng.next_u64();
+        let index = (state % 256).trailing_zeros().min(15) as usize;
+        text.push_str(WORDS[(index + (state >> 8) as usize % 3) % WORDS.len()]);
+        text.push(if state.is_multiple_of(11) { '\n' } else { ' ' });
+    }
+    text
+}
+
+fn main() {
+    let text = random_text(TEXT_LEN);
+    let encoding = create_huffman_encoding(&text).unwrap();
+    let encoded_data = encode(&encoding, &text).unwrap();
+    println!(
+        "{} bytes of text, {} bytes encoded",
+        text.len(),
+        encoded_data.data.len()
+    );
+
+    let start = Instant::now();
+    let decoded_text = decode(&encoding, &encoded_data).unwrap();
+    let bitwise = start.elapsed();
+
+    let start = Instant::now();
+    let decoder = TableDecoder::new(&encoding);
+    let build = start.elapsed();
+    let start = Instant::now();
+    let table_decoded_text = decoder.decode(&encoded_data).unwrap();
+    let table = start.elapsed();
+
+    assert_eq!(decoded_text, text);
+    assert_eq!(table_decoded_text, decoded_text);
+    println!("{:<16} {:>10}", "decode", format_duration(bitwise));
+    println!(
+        "{:<16} {:>10} (plus {} to build the table)",
+        "TableDecoder",
+        format_duration(table),
+        format_duration(build)
+    );
+}
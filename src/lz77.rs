@@ -0,0 +1,214 @@
+pub(crate) const MIN_MATCH: usize = 3;
+pub(crate) const MAX_MATCH: usize = 258;
+pub(crate) const MAX_WINDOW: usize = 1 << 15;
+
+const HASH_BITS: u32 = 15;
+const NONE: usize = usize::MAX;
+
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub(crate) enum Token {
+    Literal(u8),
+    // Copy `length` bytes starting `distance` bytes back, possibly
+    // overlapping the bytes being produced.
+    Match { length: u16, distance: u16 },
+}
+
+// Finds earlier occurrences of the upcoming bytes through chains of positions
+// that share the hash of their first three bytes, most recent first.
+#[derive(Debug, Clone, Copy)]
+pub(crate) struct MatchFinder {
+    window_size: usize,
+    max_chain: usize,
+    // Matches at least this long are taken without checking whether the next
+    // position has a longer one.
+    lazy_len: usize,
+}
+
+impl Default for MatchFinder {
+    fn default() -> Self {
+        Self::new(MAX_WINDOW)
+    }
+}
+
+impl MatchFinder {
+    pub(crate) fn new(window_size: usize) -> Self {
+        assert!(
+            (1..=MAX_WINDOW).contains(&window_size),
+            "the window must hold between 1 and {MAX_WINDOW} bytes"
+        );
+        Self {
+            window_size,
+            max_chain: 128,
+            lazy_len: 32,
+        }
+    }
+
+    // How many candidates to try per position, trading speed for ratio.
+    pub(crate) fn with_max_chain(self, max_chain: usize) -> Self {
+        Self { max_chain, ..self }
+    }
+
+    pub(crate) fn with_lazy_len(self, lazy_len: usize) -> Self {
+        Self { lazy_len, ..self }
+    }
+
+    pub(crate) fn tokens(&self, data: &[u8]) -> Vec<Token> {
+        let mut chains = HashChains::new(data.len());
+        let mut tokens = Vec::new();
+        let mut position = 0;
+        while position < data.len() {
+            let found = self.longest_match(data, &chains, position);
+            chains.insert(data, position);
+            let Some((length, distance)) = found else {
+                tokens.push(Token::Literal(data[position]));
+                position += 1;
+                continue;
+            };
+            if length < self.lazy_len {
+                let next = self.longest_match(data, &chains, position + 1);
+                if next.is_some_and(|(next_length, _)| next_length > length) {
+                    tokens.push(Token::Literal(data[position]));
+                    position += 1;
+                    continue;
+                }
+            }
+            tokens.push(Token::Match {
+                length: length as u16,
+                distance: distance as u16,
+            });
+            for skipped in position + 1..position + length {
+                chains.insert(data, skipped);
+            }
+            position += length;
+        }
+        tokens
+    }
+
+    fn longest_match(
+        &self,
+        data: &[u8],
+        chains: &HashChains,
+        position: usize,
+    ) -> Option<(usize, usize)> {
+        if position + MIN_MATCH > data.len() {
+            return None;
+        }
+        let max_length = MAX_MATCH.min(data.len() - position);
+        let mut best = None;
+        let mut best_length = MIN_MATCH - 1;
+        let mut candidate = chains.head[hash(data, position)];
+        for _ in 0..self.max_chain {
+            if candidate == NONE || position - candidate > self.window_size {
+                break;
+            }
+            // Anything shorter differs at `best_length`, which is cheap to
+            // rule out first.
+            if data[candidate + best_length] == data[position + best_length] {
+                let length = data[candidate..]
+                    .iter()
+                    .zip(&data[position..position + max_length])
+                    .take_while(|(a, b)| a == b)
+                    .count();
+                if length > best_length {
+                    best_length = length;
+                    best = Some((length, position - candidate));
+                    if length == max_length {
+                        break;
+                    }
+                }
+            }
+            candidate = chains.previous[candidate];
+        }
+        best
+    }
+}
+
+struct HashChains {
+    head: Vec<usize>,
+    previous: Vec<usize>,
+}
+
+impl HashChains {
+    fn new(len: usize) -> Self {
+        Self {
+            head: vec![NONE; 1 << HASH_BITS],
+            previous: vec![NONE; len],
+        }
+    }
+
+    fn insert(&mut self, data: &[u8], position: usize) {
+        if position + MIN_MATCH <= data.len() {
+            let hash = hash(data, position);
+            self.previous[position] = self.head[hash];
+            self.head[hash] = position;
+        }
+    }
+}
+
+fn hash(data: &[u8], position: usize) -> usize {
+    let key = u32::from_le_bytes([data[position], data[position + 1], data[position + 2], 0]);
+    (key.wrapping_mul(0x9e37_79b1) >> (u32::BITS - HASH_BITS)) as usize
+}
+
+pub(crate) fn expand(tokens: &[Token]) -> Vec<u8> {
+    let mut data = Vec::new();
+    for &token in tokens {
+        match token {
+            Token::Literal(byte) => data.push(byte),
+            Token::Match { length, distance } => {
+                let start = data.len() - distance as usize;
+                for index in start..start + length as usize {
+                    data.push(data[index]);
+                }
+            }
+        }
+    }
+    data
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn round_trips() {
+        let text = b"abcabcabcabcabcabc hello hello hello world, aaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaa";
+        let tokens = MatchFinder::default().tokens(text);
+        assert!(tokens.len() < text.len() / 2);
+        assert_eq!(expand(&tokens), text);
+
+        let data = (0..100_000u64)
+            .map(|i| (i * i % 251 / 16) as u8)
+            .collect::<Vec<_>>();
+        for finder in [
+            MatchFinder::default(),
+            MatchFinder::new(100).with_max_chain(4),
+            MatchFinder::new(1).with_lazy_len(0),
+        ] {
+            assert_eq!(expand(&finder.tokens(&data)), data);
+        }
+        assert_eq!(MatchFinder::default().tokens(&[]), []);
+    }
+
+    #[test]
+    fn respects_limits() {
+        let mut data = vec![7; 1000];
+        data.extend(b"0123456789".repeat(3));
+        let tokens = MatchFinder::new(8).tokens(&data);
+        assert_eq!(tokens[0], Token::Literal(7));
+        assert_eq!(
+            tokens[1],
+            Token::Match {
+                length: MAX_MATCH as u16,
+                distance: 1
+            }
+        );
+        // The repeats of the digits are further apart than the window.
+        for token in tokens {
+            if let Token::Match { length, distance } = token {
+                assert!(distance as usize <= 8);
+                assert!((MIN_MATCH..=MAX_MATCH).contains(&(length as usize)));
+            }
+        }
+    }
+}
@@ -1,14 +1,43 @@
 mod dynamic_time_warp;
 
-use dynamic_time_warp::dynamic_time_warp;
+use dynamic_time_warp::{
+    dynamic_time_warp, dynamic_time_warp_alignment, dynamic_time_warp_vectors,
+    dynamic_time_warp_vectors_alignment, Cosine, Euclidean, Manhattan,
+};
+use ndarray::array;
 
 fn main() {
     let dtw = dynamic_time_warp(&[7, 5, 5, 1, 5, 3], &[4, 2, 2, 2, 2]);
     println!("{:?}", dtw);
-    println!("{}", dtw.rows().into_iter().nth(0).unwrap());
+    println!("{}", dtw.rows().into_iter().next().unwrap());
     println!("{}", dtw.rows().into_iter().nth(1).unwrap());
     println!("{}", dtw.rows().into_iter().nth(2).unwrap());
     println!("{}", dtw.rows().into_iter().nth(3).unwrap());
     println!("{}", dtw.rows().into_iter().nth(4).unwrap());
     println!("{}", dtw.rows().into_iter().nth(5).unwrap());
+
+    let s = array![[0.0, 1.0], [1.0, 2.0], [2.0, 2.5], [3.0, 1.0]];
+    let q = array![[0.0, 1.2], [0.5, 1.5], [1.0, 2.0], [2.5, 2.0], [3.0, 0.5]];
+    println!(
+        "{}",
+        dynamic_time_warp_vectors(s.view(), q.view(), Euclidean)
+    );
+    println!(
+        "{}",
+        dynamic_time_warp_vectors(s.view(), q.view(), Manhattan)
+    );
+    println!("{}", dynamic_time_warp_vectors(s.view(), q.view(), Cosine));
+
+    let s1 = [7, 5, 5, 1, 5, 3];
+    let q1 = [4, 2, 2, 2, 2];
+    let alignment = dynamic_time_warp_alignment(&s1, &q1);
+    println!("distance {}, path {:?}", alignment.distance, alignment.path);
+    let (warped_s, warped_q) = alignment.warp(&s1, &q1);
+    println!("{warped_s:?}\n{warped_q:?}");
+
+    let alignment = dynamic_time_warp_vectors_alignment(s.view(), q.view(), Euclidean);
+    println!("distance {}, path {:?}", alignment.distance, alignment.path);
+    println!("{}", alignment.costs);
+    let (warped_s, warped_q) = alignment.warp_rows(s.view(), q.view());
+    println!("{warped_s}\n{warped_q}");
 }
@@ -0,0 +1,141 @@
+use std::cmp::Reverse;
+use std::collections::{BinaryHeap, HashMap, HashSet};
+
+use crate::graph::{sorted_nodes, undirected_edges, WeightedGraph};
+use crate::union_find::UnionFind;
+
+#[derive(Debug, PartialEq, Eq)]
+pub(crate) struct SpanningForest<'graph> {
+    pub(crate) edges: Vec<(&'graph str, &'graph str, u64)>,
+    pub(crate) total_weight: u64,
+    pub(crate) trees: usize,
+}
+
+impl SpanningForest<'_> {
+    pub(crate) fn is_tree(&self) -> bool {
+        self.trees <= 1
+    }
+}
+
+pub(crate) fn kruskal<UF: UnionFind, G: WeightedGraph>(graph: &G) -> SpanningForest<'_> {
+    let nodes = sorted_nodes(graph);
+    let indices = nodes
+        .iter()
+        .enumerate()
+        .map(|(index, node)| (*node, index))
+        .collect::<HashMap<_, _>>();
+    let mut uf = UF::new(nodes.len(), 0);
+    let mut edges = Vec::new();
+    let mut total_weight = 0;
+    for (from, to, weight) in undirected_edges(graph) {
+        let root_1 = uf.find(indices[from]);
+        let root_2 = uf.find(indices[to]);
+        if root_1 == root_2 {
+            continue;
+        }
+        uf.union(root_1, root_2);
+        edges.push((from, to, weight));
+        total_weight += weight;
+    }
+    SpanningForest {
+        trees: nodes.len() - edges.len(),
+        edges,
+        total_weight,
+    }
+}
+
+pub(crate) fn prim<G: WeightedGraph>(graph: &G) -> SpanningForest<'_> {
+    let mut adjacency: HashMap<&str, Vec<(&str, u64)>> = HashMap::new();
+    for (from, to, weight) in undirected_edges(graph) {
+        adjacency.entry(from).or_default().push((to, weight));
+        adjacency.entry(to).or_default().push((from, weight));
+    }
+
+    let mut visited = HashSet::new();
+    let mut queue = BinaryHeap::new();
+    let mut edges = Vec::new();
+    let mut total_weight = 0;
+    let mut trees = 0;
+    for start_node in sorted_nodes(graph) {
+        if !visited.insert(start_node) {
+            continue;
+        }
+        trees += 1;
+        for &(neighbor, weight) in adjacency.get(start_node).into_iter().flatten() {
+            queue.push(Reverse((weight, start_node, neighbor)));
+        }
+        while let Some(Reverse((weight, from, to))) = queue.pop() {
+            if !visited.insert(to) {
+                continue;
+            }
+            edges.push((from, to, weight));
+            total_weight += weight;
+            for &(neighbor, weight) in adjacency[to].iter() {
+                if !visited.contains(neighbor) {
+                    queue.push(Reverse((weight, to, neighbor)));
+                }
+            }
+        }
+    }
+    SpanningForest {
+        edges,
+        total_weight,
+        trees,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::dijkstra::Graph;
+    use crate::union_find::{UnionFindByHeight, UnionFindBySize, UnionFindBySizeWithCompression};
+
+    fn construct_graph() -> Graph {
+        let mut graph = Graph::new();
+        graph.add_edge("a", "b", 4);
+        graph.add_edge("a", "h", 8);
+        graph.add_edge("b", "c", 8);
+        graph.add_edge("b", "h", 11);
+        graph.add_edge("c", "d", 7);
+        graph.add_edge("c", "f", 4);
+        graph.add_edge("c", "i", 2);
+        graph.add_edge("d", "e", 9);
+        graph.add_edge("d", "f", 14);
+        graph.add_edge("e", "f", 10);
+        graph.add_edge("f", "g", 2);
+        graph.add_edge("g", "h", 1);
+        graph.add_edge("g", "i", 6);
+        graph.add_edge("h", "i", 7);
+        graph
+    }
+
+    #[test]
+    fn kruskal_and_prim_agree_on_weight() {
+        let graph = construct_graph();
+        let by_size = kruskal::<UnionFindBySize, _>(&graph);
+        let by_height = kruskal::<UnionFindByHeight, _>(&graph);
+        let with_compression = kruskal::<UnionFindBySizeWithCompression, _>(&graph);
+        let prim = prim(&graph);
+        for forest in [&by_size, &by_height, &with_compression, &prim] {
+            assert_eq!(forest.total_weight, 37);
+            assert_eq!(forest.edges.len(), 8);
+            assert!(forest.is_tree());
+        }
+        assert_eq!(by_size, by_height);
+        assert_eq!(by_size, with_compression);
+    }
+
+    #[test]
+    fn disconnected_graph_gives_forest() {
+        let mut graph = construct_graph();
+        graph.add_edge("x", "y", 3);
+        graph.add_edge("y", "x", 1);
+        graph.add_node("z");
+        for forest in [kruskal::<UnionFindBySize, _>(&graph), prim(&graph)] {
+            assert_eq!(forest.trees, 3);
+            assert!(!forest.is_tree());
+            assert_eq!(forest.total_weight, 38);
+            assert_eq!(forest.edges.len(), 9);
+        }
+    }
+}
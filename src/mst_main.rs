@@ -0,0 +1,56 @@
+mod dijkstra;
+mod graph;
+mod mst;
+mod union_find;
+
+use dijkstra::Graph;
+use mst::{kruskal, prim, SpanningForest};
+use union_find::UnionFindBySizeWithCompression;
+
+fn construct_graph() -> Graph {
+    let mut graph = Graph::new();
+    graph.add_edge("a", "b", 2);
+    graph.add_edge("a", "e", 3);
+    graph.add_edge("a", "f", 7);
+
+    graph.add_edge("b", "c", 2);
+
+    graph.add_edge("c", "h", 4);
+
+    graph.add_edge("d", "a", 1);
+    graph.add_edge("d", "j", 6);
+
+    graph.add_edge("e", "d", 4);
+    graph.add_edge("e", "f", 3);
+    graph.add_edge("e", "j", 2);
+
+    graph.add_edge("f", "c", 2);
+    graph.add_edge("f", "g", 4);
+
+    graph.add_edge("g", "c", 1);
+    graph.add_edge("g", "h", 1);
+
+    // X and Y are not connected to the rest of the graph.
+    graph.add_edge("x", "y", 5);
+
+    graph
+}
+
+fn print_forest(forest: &SpanningForest, algorithm_name: &str) {
+    println!("Using algorithm: {}", algorithm_name);
+    for (from, to, weight) in forest.edges.iter() {
+        println!("{from} - {to}: {weight}");
+    }
+    println!("Total weight: {}", forest.total_weight);
+    println!("Number of trees: {}", forest.trees);
+    println!();
+}
+
+fn main() {
+    let graph = construct_graph();
+    print_forest(
+        &kruskal::<UnionFindBySizeWithCompression, _>(&graph),
+        "Kruskal",
+    );
+    print_forest(&prim(&graph), "Prim");
+}
@@ -3,6 +3,8 @@ use std::{
     num::NonZeroU64,
 };
 
+use crate::graph::WeightedGraph;
+
 pub(crate) struct NetworkGraph {
     edges: HashMap<String, HashMap<String, u64>>,
 }
@@ -28,6 +30,20 @@ impl NetworkGraph {
     }
 }
 
+impl WeightedGraph for NetworkGraph {
+    fn nodes(&self) -> impl Iterator<Item = &str> {
+        self.edges.keys().map(String::as_str)
+    }
+
+    fn neighbors(&self, node: &str) -> impl Iterator<Item = (&str, u64)> {
+        self.edges
+            .get(node)
+            .into_iter()
+            .flatten()
+            .map(|(neighbor, capacity)| (neighbor.as_str(), *capacity))
+    }
+}
+
 fn find_path<'graph>(
     source: &'graph str,
     target: &'graph str,
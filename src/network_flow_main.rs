@@ -1,3 +1,4 @@
+mod graph;
 mod network_flow;
 mod huffman;
 
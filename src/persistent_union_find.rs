@@ -0,0 +1,421 @@
+use std::fmt::{Debug, Formatter, Result as FmtResult};
+use std::rc::Rc;
+
+use crate::union_find::{
+    check_bounds, debug_union_find, find_root, validate_nodes, ByRank, Linking, Members, Roots,
+    UnionFind, UnionFindError,
+};
+
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+enum Change {
+    Set(usize, isize),
+    Push,
+}
+
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub(crate) struct Snapshot(usize);
+
+// Union by rank without path compression, so every union touches at most two
+// entries and can be undone by restoring them.
+#[derive(PartialEq, Eq, Default)]
+pub(crate) struct RollbackUnionFind {
+    nodes: Vec<isize>,
+    starting_index: usize,
+    history: Vec<Change>,
+}
+
+impl Debug for RollbackUnionFind {
+    fn fmt(&self, f: &mut Formatter<'_>) -> FmtResult {
+        debug_union_find(&self.nodes, self.starting_index as isize, f)
+    }
+}
+
+impl RollbackUnionFind {
+    pub(crate) fn snapshot(&self) -> Snapshot {
+        Snapshot(self.history.len())
+    }
+
+    pub(crate) fn rollback(&mut self, snapshot: Snapshot) {
+        assert!(
+            snapshot.0 <= self.history.len(),
+            "snapshot is newer than the current state"
+        );
+        while self.history.len() > snapshot.0 {
+            match self.history.pop().unwrap() {
+                Change::Set(index, value) => self.nodes[index] = value,
+                Change::Push => {
+                    self.nodes.pop();
+                }
+            }
+        }
+    }
+}
+
+impl UnionFind for RollbackUnionFind {
+    fn new(n: usize, starting_index: usize) -> Self {
+        Self {
+            nodes: vec![-1; n],
+            starting_index,
+            history: Vec::new(),
+        }
+    }
+    fn find(&mut self, x: usize) -> usize {
+        self.find_immutable(x)
+    }
+    fn union(&mut self, root_1: usize, root_2: usize) {
+        let root_1 = self.find(root_1) - self.starting_index;
+        let root_2 = self.find(root_2) - self.starting_index;
+        if root_1 == root_2 {
+            return;
+        }
+        self.history.push(Change::Set(root_1, self.nodes[root_1]));
+        self.history.push(Change::Set(root_2, self.nodes[root_2]));
+        ByRank::link(&mut self.nodes, root_1, root_2);
+    }
+    fn roots(&self) -> Roots<'_> {
+        Roots::new(&self.nodes, self.starting_index)
+    }
+    fn from_nodes_and_starting_index(nodes: Box<[isize]>, starting_index: usize) -> Self {
+        Self {
+            nodes: nodes.into_vec(),
+            starting_index,
+            history: Vec::new(),
+        }
+    }
+    fn find_immutable(&self, x: usize) -> usize {
+        assert!(
+            x >= self.starting_index && x - self.starting_index < self.nodes.len(),
+            "out of bounds"
+        );
+        find_root(&self.nodes, x - self.starting_index) + self.starting_index
+    }
+    fn members(&self, x: usize) -> Members<'_> {
+        Members::new(&self.nodes, self.starting_index, self.find_immutable(x))
+    }
+    fn try_find(&mut self, x: usize) -> Result<usize, UnionFindError> {
+        check_bounds(x, self.starting_index, self.nodes.len())?;
+        Ok(self.find(x))
+    }
+    fn try_union(&mut self, root_1: usize, root_2: usize) -> Result<(), UnionFindError> {
+        check_bounds(root_1, self.starting_index, self.nodes.len())?;
+        check_bounds(root_2, self.starting_index, self.nodes.len())?;
+        self.union(root_1, root_2);
+        Ok(())
+    }
+    fn try_from_nodes_and_starting_index(
+        nodes: Box<[isize]>,
+        starting_index: usize,
+    ) -> Result<Self, UnionFindError> {
+        for (root, shape) in validate_nodes(&nodes, starting_index)? {
+            ByRank::validate_root(root + starting_index, nodes[root], shape.size, shape.height)?;
+        }
+        Ok(Self::from_nodes_and_starting_index(nodes, starting_index))
+    }
+    fn make_set(&mut self) -> usize {
+        self.history.push(Change::Push);
+        self.nodes.push(-1);
+        self.nodes.len() - 1 + self.starting_index
+    }
+    fn reserve(&mut self, additional: usize) {
+        self.nodes.reserve(additional);
+    }
+    fn len(&self) -> usize {
+        self.nodes.len()
+    }
+    fn starting_index(&self) -> usize {
+        self.starting_index
+    }
+    fn nodes(&self) -> &[isize] {
+        &self.nodes
+    }
+}
+
+enum PersistentNode {
+    Leaf(isize),
+    Branch(Rc<PersistentNode>, Rc<PersistentNode>),
+}
+
+// A binary tree over a power of two number of leaves. Updates copy the path to
+// the changed leaf and share everything else with the previous version.
+#[derive(Clone)]
+struct PersistentArray {
+    root: Rc<PersistentNode>,
+    capacity: usize,
+    len: usize,
+}
+
+impl PersistentArray {
+    fn filled(capacity: usize, value: isize) -> Rc<PersistentNode> {
+        let mut node = Rc::new(PersistentNode::Leaf(value));
+        let mut size = 1;
+        while size < capacity {
+            node = Rc::new(PersistentNode::Branch(node.clone(), node));
+            size *= 2;
+        }
+        node
+    }
+
+    fn from_slice(values: &[isize]) -> Self {
+        fn build(values: &[isize], capacity: usize) -> Rc<PersistentNode> {
+            if values.is_empty() {
+                return PersistentArray::filled(capacity, -1);
+            }
+            if capacity == 1 {
+                return Rc::new(PersistentNode::Leaf(values[0]));
+            }
+            let half = capacity / 2;
+            let (left, right) = values.split_at(half.min(values.len()));
+            Rc::new(PersistentNode::Branch(
+                build(left, half),
+                build(right, half),
+            ))
+        }
+        let capacity = values.len().next_power_of_two();
+        Self {
+            root: build(values, capacity),
+            capacity,
+            len: values.len(),
+        }
+    }
+
+    fn get(&self, mut index: usize) -> isize {
+        let mut node = &self.root;
+        let mut half = self.capacity / 2;
+        loop {
+            match node.as_ref() {
+                PersistentNode::Leaf(value) => return *value,
+                PersistentNode::Branch(left, right) => {
+                    if index < half {
+                        node = left;
+                    } else {
+                        node = right;
+                        index -= half;
+                    }
+                    half /= 2;
+                }
+            }
+        }
+    }
+
+    fn set(&mut self, index: usize, value: isize) {
+        fn recurse(
+            node: &PersistentNode,
+            half: usize,
+            index: usize,
+            value: isize,
+        ) -> Rc<PersistentNode> {
+            match node {
+                PersistentNode::Leaf(_) => Rc::new(PersistentNode::Leaf(value)),
+                PersistentNode::Branch(left, right) if index < half => Rc::new(
+                    PersistentNode::Branch(recurse(left, half / 2, index, value), right.clone()),
+                ),
+                PersistentNode::Branch(left, right) => Rc::new(PersistentNode::Branch(
+                    left.clone(),
+                    recurse(right, half / 2, index - half, value),
+                )),
+            }
+        }
+        self.root = recurse(&self.root, self.capacity / 2, index, value);
+    }
+
+    fn push(&mut self, value: isize) {
+        if self.len == self.capacity {
+            self.root = Rc::new(PersistentNode::Branch(
+                self.root.clone(),
+                Self::filled(self.capacity, -1),
+            ));
+            self.capacity *= 2;
+        }
+        self.set(self.len, value);
+        self.len += 1;
+    }
+
+    fn to_vec(&self) -> Vec<isize> {
+        (0..self.len).map(|index| self.get(index)).collect()
+    }
+}
+
+// Every operation returns a new version and leaves `self` untouched, so any
+// number of past versions stay queryable and can be branched from. Cloning a
+// version is O(1). The trait is not implemented because `roots` and `members`
+// need the nodes as a contiguous slice.
+#[derive(Clone)]
+pub(crate) struct PersistentUnionFind {
+    nodes: PersistentArray,
+    starting_index: usize,
+    sets: usize,
+}
+
+impl Debug for PersistentUnionFind {
+    fn fmt(&self, f: &mut Formatter<'_>) -> FmtResult {
+        debug_union_find(&self.nodes.to_vec(), self.starting_index as isize, f)
+    }
+}
+
+impl PersistentUnionFind {
+    pub(crate) fn new(n: usize, starting_index: usize) -> Self {
+        Self {
+            nodes: PersistentArray::from_slice(&vec![-1; n]),
+            starting_index,
+            sets: n,
+        }
+    }
+
+    pub(crate) fn find(&self, x: usize) -> usize {
+        assert!(
+            x >= self.starting_index && x - self.starting_index < self.nodes.len,
+            "out of bounds"
+        );
+        let mut x = x - self.starting_index;
+        loop {
+            let parent = self.nodes.get(x);
+            if parent < 0 {
+                return x + self.starting_index;
+            }
+            x = parent as usize;
+        }
+    }
+
+    pub(crate) fn try_find(&self, x: usize) -> Result<usize, UnionFindError> {
+        check_bounds(x, self.starting_index, self.nodes.len)?;
+        Ok(self.find(x))
+    }
+
+    pub(crate) fn union(&self, root_1: usize, root_2: usize) -> Self {
+        let root_1 = self.find(root_1) - self.starting_index;
+        let root_2 = self.find(root_2) - self.starting_index;
+        let mut next = self.clone();
+        if root_1 == root_2 {
+            return next;
+        }
+        let rank_1 = self.nodes.get(root_1);
+        let rank_2 = self.nodes.get(root_2);
+        let (bigger, smaller) = if rank_2 < rank_1 {
+            (root_2, root_1)
+        } else {
+            (root_1, root_2)
+        };
+        if rank_1 == rank_2 {
+            next.nodes.set(bigger, rank_1 - 1);
+        }
+        next.nodes.set(smaller, bigger as isize);
+        next.sets -= 1;
+        next
+    }
+
+    pub(crate) fn try_union(&self, root_1: usize, root_2: usize) -> Result<Self, UnionFindError> {
+        check_bounds(root_1, self.starting_index, self.nodes.len)?;
+        check_bounds(root_2, self.starting_index, self.nodes.len)?;
+        Ok(self.union(root_1, root_2))
+    }
+
+    pub(crate) fn make_set(&self) -> (Self, usize) {
+        let mut next = self.clone();
+        next.nodes.push(-1);
+        next.sets += 1;
+        (next, self.nodes.len + self.starting_index)
+    }
+
+    pub(crate) fn same_set(&self, a: usize, b: usize) -> bool {
+        self.find(a) == self.find(b)
+    }
+
+    pub(crate) fn num_sets(&self) -> usize {
+        self.sets
+    }
+
+    pub(crate) fn len(&self) -> usize {
+        self.nodes.len
+    }
+
+    pub(crate) fn is_empty(&self) -> bool {
+        self.nodes.len == 0
+    }
+}
+
+impl From<&RollbackUnionFind> for PersistentUnionFind {
+    fn from(value: &RollbackUnionFind) -> Self {
+        Self {
+            nodes: PersistentArray::from_slice(&value.nodes),
+            starting_index: value.starting_index,
+            sets: value.num_sets(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn rollback_restores_earlier_states() {
+        let mut uf = RollbackUnionFind::new(6, 1);
+        uf.union(1, 2);
+        let before = uf.snapshot();
+        let expected = format!("{uf:?}");
+        uf.union(3, 4);
+        uf.union(2, 4);
+        let added = uf.make_set();
+        uf.union(added, 6);
+        assert_eq!(added, 7);
+        assert!(uf.same_set(1, 3));
+        assert_eq!(uf.num_sets(), 3);
+
+        uf.rollback(before);
+        assert_eq!(format!("{uf:?}"), expected);
+        assert_eq!(uf.len(), 6);
+        assert!(uf.same_set(1, 2));
+        assert!(!uf.same_set(1, 3));
+        assert_eq!(uf.num_sets(), 5);
+
+        uf.rollback(Snapshot(0));
+        assert_eq!(uf, RollbackUnionFind::new(6, 1));
+    }
+
+    #[test]
+    fn persistent_versions_stay_queryable() {
+        let empty = PersistentUnionFind::new(5, 0);
+        let first = empty.union(0, 1);
+        let second = first.union(2, 3);
+        let branch = first.union(1, 4);
+        let merged = second.union(0, 3);
+
+        assert_eq!(empty.num_sets(), 5);
+        assert!(!empty.same_set(0, 1));
+        assert!(first.same_set(0, 1));
+        assert!(!first.same_set(2, 3));
+        assert!(second.same_set(2, 3));
+        assert!(!second.same_set(1, 4));
+        assert!(branch.same_set(0, 4));
+        assert!(!branch.same_set(2, 3));
+        assert!(merged.same_set(1, 2));
+        assert_eq!(merged.num_sets(), 2);
+        assert_eq!(format!("{empty:?}"), "[-1, -1, -1, -1, -1]");
+    }
+
+    #[test]
+    fn persistent_growth_and_conversion() {
+        let mut uf = RollbackUnionFind::new(3, 1);
+        uf.union(1, 3);
+        let base = PersistentUnionFind::from(&uf);
+        let mut grown = base.clone();
+        for expected in 4..40 {
+            let (next, index) = grown.make_set();
+            assert_eq!(index, expected);
+            grown = next.union(index, index - 1);
+        }
+        assert_eq!(base.len(), 3);
+        assert_eq!(grown.len(), 39);
+        assert_eq!(grown.num_sets(), 2);
+        assert!(grown.same_set(4, 39));
+        assert!(!grown.same_set(2, 39));
+        assert_eq!(
+            grown.try_find(40),
+            Err(UnionFindError::OutOfBounds {
+                index: 40,
+                starting_index: 1,
+                len: 39
+            })
+        );
+    }
+}
@@ -0,0 +1,32 @@
+use persistent_union_find::{PersistentUnionFind, RollbackUnionFind};
+use union_find::UnionFind;
+
+mod persistent_union_find;
+mod union_find;
+
+fn main() {
+    let mut uf = RollbackUnionFind::new(6, 1);
+    uf.union(1, 2);
+    println!("After inserting (1, 2): {uf:?}");
+    let snapshot = uf.snapshot();
+    for (a, b) in [(3, 4), (5, 6), (2, 4)] {
+        uf.union(a, b);
+        println!("After inserting ({a}, {b}): {uf:?}");
+    }
+    uf.rollback(snapshot);
+    println!("After rolling back: {uf:?}");
+    println!();
+
+    let mut versions = vec![PersistentUnionFind::new(6, 1)];
+    for (a, b) in [(1, 2), (3, 4), (5, 6), (2, 4)] {
+        let next = versions.last().unwrap().union(a, b);
+        versions.push(next);
+    }
+    for (i, version) in versions.iter().enumerate() {
+        println!(
+            "Version {i}: {version:?}, number of sets: {}, 1 and 3 connected: {}",
+            version.num_sets(),
+            version.same_set(1, 3)
+        );
+    }
+}
@@ -0,0 +1,554 @@
+use std::collections::{HashMap, HashSet};
+use std::error::Error;
+use std::fmt::{Display, Formatter, Result as FmtResult};
+use std::hash::Hash;
+
+use crate::entropy_coder::EntropyCoder;
+use crate::huffman::EncodedData;
+
+// Frequencies are kept to 16 bits so that with the range never below 2^24
+// there are always at least 8 bits of precision left per symbol.
+const MAX_TOTAL: u32 = 1 << 16;
+const TOP: u32 = 1 << 24;
+
+// How often adaptive models count a symbol, relative to the one every symbol
+// starts with.
+const ADAPTIVE_INCREMENT: u32 = 24;
+
+// Symbols that take up no room, as with a model of a single symbol, let a
+// short input claim any number of them, so decoding stops at this many.
+const DEFAULT_MAX_LEN: u64 = 1 << 28;
+
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub(crate) enum RangeCoderError {
+    UnknownSymbol { index: usize },
+    TruncatedStream,
+    EmptyModel,
+    TooLong { len: u64, max_len: u64 },
+}
+
+impl Display for RangeCoderError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> FmtResult {
+        match self {
+            RangeCoderError::UnknownSymbol { index } => {
+                write!(f, "the symbol at index {index} is not in the model")
+            }
+            RangeCoderError::TruncatedStream => write!(f, "the data ends too early"),
+            RangeCoderError::EmptyModel => write!(f, "the model has no symbols to decode"),
+            RangeCoderError::TooLong { len, max_len } => {
+                write!(f, "{len} symbols is more than the limit of {max_len}")
+            }
+        }
+    }
+}
+
+impl Error for RangeCoderError {}
+
+// Gives every symbol a slice [cumulative, cumulative + frequency) of
+// [0, total).
+pub(crate) trait FrequencyModel<S>: Clone {
+    fn total(&self) -> u32;
+    // The index, cumulative frequency and frequency of `symbol`.
+    fn lookup(&self, symbol: S) -> Option<(usize, u32, u32)>;
+    // The symbol whose slice contains `value`, and its index, cumulative
+    // frequency and frequency.
+    fn find(&self, value: u32) -> (S, usize, u32, u32);
+    fn update(&mut self, index: usize);
+}
+
+#[derive(Debug, Clone)]
+struct Frequencies<S> {
+    symbols: Vec<S>,
+    indices: HashMap<S, usize>,
+    frequencies: Vec<u32>,
+    // One longer than `frequencies`, the last entry is the total.
+    cumulative: Vec<u32>,
+}
+
+impl<S: Hash + Eq + Copy> Frequencies<S> {
+    fn new(symbols: Vec<S>, frequencies: Vec<u32>) -> Self {
+        let indices = symbols
+            .iter()
+            .enumerate()
+            .map(|(index, &symbol)| (symbol, index))
+            .collect();
+        let mut model = Self {
+            symbols,
+            indices,
+            frequencies,
+            cumulative: Vec::new(),
+        };
+        model.accumulate(0);
+        model
+    }
+
+    fn accumulate(&mut self, from: usize) {
+        self.cumulative.truncate(from + 1);
+        if self.cumulative.is_empty() {
+            self.cumulative.push(0);
+        }
+        for index in from..self.frequencies.len() {
+            self.cumulative
+                .push(self.cumulative[index] + self.frequencies[index]);
+        }
+    }
+
+    fn lookup(&self, symbol: S) -> Option<(usize, u32, u32)> {
+        let &index = self.indices.get(&symbol)?;
+        Some((index, self.cumulative[index], self.frequencies[index]))
+    }
+
+    fn find(&self, value: u32) -> (S, usize, u32, u32) {
+        let index = self
+            .cumulative
+            .partition_point(|&cumulative| cumulative <= value)
+            - 1;
+        (
+            self.symbols[index],
+            index,
+            self.cumulative[index],
+            self.frequencies[index],
+        )
+    }
+}
+
+// Frequencies fixed up front, typically counted from the data itself.
+#[derive(Debug, Clone)]
+pub(crate) struct StaticModel<S>(Frequencies<S>);
+
+impl<S: Hash + Eq + Copy> StaticModel<S> {
+    // Frequencies are scaled down to fit in 16 bits, keeping every symbol
+    // encodable.
+    pub(crate) fn from_frequencies<I>(frequencies: I) -> Self
+    where
+        I: IntoIterator<Item = (S, u64)>,
+    {
+        let (symbols, counts): (Vec<_>, Vec<_>) = frequencies
+            .into_iter()
+            .filter(|&(_, count)| count > 0)
+            .unzip();
+        assert!(
+            symbols.len() <= MAX_TOTAL as usize / 2,
+            "too many symbols for a range coder model"
+        );
+        // Counts can be anywhere up to `u64::MAX`, so neither their sum nor
+        // the scaled counts fit in a u64.
+        let total = counts.iter().map(|&count| count as u128).sum::<u128>();
+        let target = (MAX_TOTAL as usize - symbols.len()) as u128;
+        let frequencies = counts
+            .into_iter()
+            .map(|count| {
+                if total <= MAX_TOTAL as u128 {
+                    count as u32
+                } else {
+                    (count as u128 * target / total).max(1) as u32
+                }
+            })
+            .collect();
+        Self(Frequencies::new(symbols, frequencies))
+    }
+
+    pub(crate) fn from_symbols<I>(symbols: I) -> Self
+    where
+        I: IntoIterator<Item = S>,
+    {
+        let mut indices = HashMap::new();
+        let mut frequencies = Vec::new();
+        for symbol in symbols {
+            let index = *indices.entry(symbol).or_insert_with(|| {
+                frequencies.push((symbol, 0));
+                frequencies.len() - 1
+            });
+            frequencies[index].1 += 1;
+        }
+        Self::from_frequencies(frequencies)
+    }
+}
+
+impl<S: Hash + Eq + Copy> FrequencyModel<S> for StaticModel<S> {
+    fn total(&self) -> u32 {
+        *self.0.cumulative.last().unwrap()
+    }
+
+    fn lookup(&self, symbol: S) -> Option<(usize, u32, u32)> {
+        self.0.lookup(symbol)
+    }
+
+    fn find(&self, value: u32) -> (S, usize, u32, u32) {
+        self.0.find(value)
+    }
+
+    fn update(&mut self, _index: usize) {}
+}
+
+// Every symbol of a fixed alphabet starts out equally likely, and is counted
+// as it is coded. Counts are halved when the total gets too large, which also
+// lets the model follow data whose statistics drift.
+#[derive(Debug, Clone)]
+pub(crate) struct AdaptiveModel<S>(Frequencies<S>);
+
+impl<S: Hash + Eq + Copy> AdaptiveModel<S> {
+    pub(crate) fn new<I>(alphabet: I) -> Self
+    where
+        I: IntoIterator<Item = S>,
+    {
+        let mut seen = HashSet::new();
+        let symbols = alphabet
+            .into_iter()
+            .filter(|&symbol| seen.insert(symbol))
+            .collect::<Vec<_>>();
+        assert!(
+            symbols.len() <= (MAX_TOTAL / ADAPTIVE_INCREMENT) as usize,
+            "too many symbols for an adaptive range coder model"
+        );
+        let frequencies = vec![1; symbols.len()];
+        Self(Frequencies::new(symbols, frequencies))
+    }
+}
+
+impl AdaptiveModel<u8> {
+    pub(crate) fn bytes() -> Self {
+        Self::new(0..=255)
+    }
+}
+
+impl<S: Hash + Eq + Copy> FrequencyModel<S> for AdaptiveModel<S> {
+    fn total(&self) -> u32 {
+        *self.0.cumulative.last().unwrap()
+    }
+
+    fn lookup(&self, symbol: S) -> Option<(usize, u32, u32)> {
+        self.0.lookup(symbol)
+    }
+
+    fn find(&self, value: u32) -> (S, usize, u32, u32) {
+        self.0.find(value)
+    }
+
+    fn update(&mut self, index: usize) {
+        self.0.frequencies[index] += ADAPTIVE_INCREMENT;
+        if self.total() + ADAPTIVE_INCREMENT > MAX_TOTAL {
+            for frequency in self.0.frequencies.iter_mut() {
+                *frequency = (*frequency).div_ceil(2);
+            }
+            self.0.accumulate(0);
+        } else {
+            self.0.accumulate(index);
+        }
+    }
+}
+
+// A range coder in the style of LZMA's: `low` has a spare 33rd bit to catch
+// carries, and the byte below it is held back in `cache`, along with any 0xff
+// bytes after it, until it is known whether a carry will reach it.
+struct RangeEncoder {
+    low: u64,
+    range: u32,
+    cache: u8,
+    cache_size: u64,
+    output: Vec<u8>,
+}
+
+impl RangeEncoder {
+    fn new() -> Self {
+        Self {
+            low: 0,
+            range: u32::MAX,
+            cache: 0,
+            cache_size: 1,
+            output: Vec::new(),
+        }
+    }
+
+    fn encode(&mut self, cumulative: u32, frequency: u32, total: u32) {
+        let r = self.range / total;
+        self.low += r as u64 * cumulative as u64;
+        self.range = r * frequency;
+        while self.range < TOP {
+            self.range <<= 8;
+            self.shift_low();
+        }
+    }
+
+    fn shift_low(&mut self) {
+        if self.low < 0xff00_0000 || self.low > u32::MAX as u64 {
+            let carry = (self.low >> 32) as u8;
+            let mut byte = self.cache;
+            loop {
+                self.output.push(byte.wrapping_add(carry));
+                byte = 0xff;
+                self.cache_size -= 1;
+                if self.cache_size == 0 {
+                    break;
+                }
+            }
+            self.cache = (self.low >> 24) as u8;
+        }
+        self.cache_size += 1;
+        self.low = (self.low & 0x00ff_ffff) << 8;
+    }
+
+    fn finish(mut self) -> Vec<u8> {
+        for _ in 0..5 {
+            self.shift_low();
+        }
+        self.output
+    }
+}
+
+struct RangeDecoder<'data> {
+    input: &'data [u8],
+    code: u32,
+    range: u32,
+}
+
+impl<'data> RangeDecoder<'data> {
+    fn new(input: &'data [u8]) -> Result<Self, RangeCoderError> {
+        let mut decoder = Self {
+            input,
+            code: 0,
+            range: u32::MAX,
+        };
+        // The first byte is always the encoder's initial, empty, cache.
+        for _ in 0..5 {
+            decoder.code = (decoder.code << 8) | decoder.next_byte()? as u32;
+        }
+        Ok(decoder)
+    }
+
+    fn next_byte(&mut self) -> Result<u8, RangeCoderError> {
+        let (&byte, rest) = self
+            .input
+            .split_first()
+            .ok_or(RangeCoderError::TruncatedStream)?;
+        self.input = rest;
+        Ok(byte)
+    }
+
+    fn value(&mut self, total: u32) -> u32 {
+        self.range /= total;
+        (self.code / self.range).min(total - 1)
+    }
+
+    // Must follow `value`, which leaves the range divided by the total.
+    fn consume(&mut self, cumulative: u32, frequency: u32) -> Result<(), RangeCoderError> {
+        self.code -= self.range * cumulative;
+        self.range *= frequency;
+        while self.range < TOP {
+            self.range <<= 8;
+            self.code = (self.code << 8) | self.next_byte()? as u32;
+        }
+        Ok(())
+    }
+}
+
+// Codes symbols with `model`, which adaptive models start afresh from for
+// every message. The output starts with the number of symbols, as a u64 in
+// little endian, because unlike a prefix code the range coder cannot tell
+// where the data ends on its own.
+#[derive(Debug, Clone)]
+pub(crate) struct RangeCoder<M> {
+    model: M,
+    max_len: u64,
+}
+
+pub(crate) type StaticRangeCoder<S = char> = RangeCoder<StaticModel<S>>;
+pub(crate) type AdaptiveRangeCoder<S = char> = RangeCoder<AdaptiveModel<S>>;
+
+impl<M> RangeCoder<M> {
+    pub(crate) fn new(model: M) -> Self {
+        Self {
+            model,
+            max_len: DEFAULT_MAX_LEN,
+        }
+    }
+
+    // The most symbols a message may hold, which bounds the work and memory
+    // spent decoding untrusted data.
+    pub(crate) fn with_max_len(self, max_len: u64) -> Self {
+        Self { max_len, ..self }
+    }
+
+    fn check_len(&self, len: u64) -> Result<(), RangeCoderError> {
+        if len > self.max_len {
+            Err(RangeCoderError::TooLong {
+                len,
+                max_len: self.max_len,
+            })
+        } else {
+            Ok(())
+        }
+    }
+}
+
+impl<S: Hash + Eq + Copy, M: FrequencyModel<S>> EntropyCoder<S> for RangeCoder<M> {
+    type Error = RangeCoderError;
+
+    fn encode(&self, symbols: &[S]) -> Result<EncodedData, RangeCoderError> {
+        self.check_len(symbols.len() as u64)?;
+        let mut model = self.model.clone();
+        let mut encoder = RangeEncoder::new();
+        for (index, &symbol) in symbols.iter().enumerate() {
+            let (symbol_index, cumulative, frequency) = model
+                .lookup(symbol)
+                .ok_or(RangeCoderError::UnknownSymbol { index })?;
+            encoder.encode(cumulative, frequency, model.total());
+            model.update(symbol_index);
+        }
+        let mut data = (symbols.len() as u64).to_le_bytes().to_vec();
+        data.extend(encoder.finish());
+        Ok(EncodedData {
+            data,
+            last_byte_len: 0,
+        })
+    }
+
+    fn decode(&self, encoded_data: &EncodedData) -> Result<Vec<S>, RangeCoderError> {
+        let (len, data) = encoded_data
+            .data
+            .split_first_chunk::<8>()
+            .ok_or(RangeCoderError::TruncatedStream)?;
+        let len = u64::from_le_bytes(*len);
+        self.check_len(len)?;
+        let mut model = self.model.clone();
+        if len > 0 && model.total() == 0 {
+            return Err(RangeCoderError::EmptyModel);
+        }
+        let mut decoder = RangeDecoder::new(data)?;
+        // The length is untrusted, so it does not size the allocation.
+        let mut result = Vec::with_capacity(len.min(data.len() as u64 * 8) as usize);
+        for _ in 0..len {
+            let value = decoder.value(model.total());
+            let (symbol, symbol_index, cumulative, frequency) = model.find(value);
+            decoder.consume(cumulative, frequency)?;
+            model.update(symbol_index);
+            result.push(symbol);
+        }
+        Ok(result)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::bench_util::XorShift;
+    use crate::huffman::create_huffman_tree_from_symbols;
+    use crate::huffman::HuffmanEncoding;
+
+    fn skewed_bytes(len: usize) -> Vec<u8> {
+        let mut rng = XorShift::default();
+        (0..len)
+            // Mostly zeros, which Huffman cannot code in under a bit.
+            .map(|_| match rng.next_u64() % 100 {
+                0..=89 => 0,
+                90..=96 => 1,
+                value => value as u8,
+            })
+            .collect()
+    }
+
+    #[test]
+    fn round_trips() {
+        let text = "This is test data for encoding and decoding! Ünïcödé too."
+            .chars()
+            .collect::<Vec<_>>();
+        let coder = StaticRangeCoder::new(StaticModel::from_symbols(text.iter().copied()));
+        let encoded = coder.encode(&text).unwrap();
+        assert_eq!(coder.decode(&encoded).unwrap(), text);
+
+        let data = skewed_bytes(10_000);
+        let coder = RangeCoder::new(AdaptiveModel::bytes());
+        let encoded = coder.encode(&data).unwrap();
+        assert_eq!(coder.decode(&encoded).unwrap(), data);
+
+        // Enough symbols to make the adaptive model rescale many times.
+        let data = (0..200_000u64)
+            .map(|i| (i * i % 251) as u8)
+            .collect::<Vec<_>>();
+        let encoded = coder.encode(&data).unwrap();
+        assert_eq!(coder.decode(&encoded).unwrap(), data);
+
+        let empty = coder.encode(&[]).unwrap();
+        assert_eq!(coder.decode(&empty).unwrap(), []);
+
+        // Counts this large overflow a u64 once summed or scaled.
+        let model = StaticModel::from_frequencies([('a', u64::MAX), ('b', 1 << 50), ('c', 1)]);
+        let coder = StaticRangeCoder::new(model);
+        let text = "aaaabaaaacaaab".chars().collect::<Vec<_>>();
+        let encoded = coder.encode(&text).unwrap();
+        assert_eq!(coder.decode(&encoded).unwrap(), text);
+    }
+
+    #[test]
+    fn beats_huffman_on_skewed_data() {
+        let data = skewed_bytes(100_000);
+        let huffman =
+            HuffmanEncoding::new(&create_huffman_tree_from_symbols(data.iter().copied()).unwrap());
+        let static_coder = StaticRangeCoder::new(StaticModel::from_symbols(data.iter().copied()));
+        let adaptive_coder = AdaptiveRangeCoder::new(AdaptiveModel::bytes());
+
+        let huffman_rate = huffman.rate(&data).unwrap();
+        let static_rate = static_coder.rate(&data).unwrap();
+        let adaptive_rate = adaptive_coder.rate(&data).unwrap();
+        assert!(huffman_rate >= 1.0);
+        assert!(static_rate < 0.8, "{static_rate}");
+        assert!(adaptive_rate < 0.8, "{adaptive_rate}");
+    }
+
+    #[test]
+    fn reports_errors() {
+        let coder = StaticRangeCoder::new(StaticModel::from_symbols("abc".chars()));
+        assert_eq!(
+            coder.encode(&['a', 'b', 'd']).unwrap_err(),
+            RangeCoderError::UnknownSymbol { index: 2 }
+        );
+        let text = "abcabcabccba".chars().collect::<Vec<_>>();
+        let mut encoded = coder.encode(&text).unwrap();
+        encoded.data.truncate(encoded.data.len() - 5);
+        assert_eq!(
+            coder.decode(&encoded).unwrap_err(),
+            RangeCoderError::TruncatedStream
+        );
+        encoded.data.truncate(4);
+        assert_eq!(
+            coder.decode(&encoded).unwrap_err(),
+            RangeCoderError::TruncatedStream
+        );
+
+        let mut untrusted = 1u64.to_le_bytes().to_vec();
+        untrusted.extend([0; 5]);
+        let untrusted = EncodedData {
+            data: untrusted,
+            last_byte_len: 0,
+        };
+        let empty = StaticRangeCoder::new(StaticModel::from_symbols("".chars()));
+        assert_eq!(
+            empty.decode(&untrusted).unwrap_err(),
+            RangeCoderError::EmptyModel
+        );
+
+        // A lone symbol costs nothing, so only the limit stops this.
+        let lone = StaticRangeCoder::new(StaticModel::from_symbols("a".chars())).with_max_len(100);
+        let mut untrusted = untrusted;
+        untrusted.data[..8].copy_from_slice(&u64::MAX.to_le_bytes());
+        assert_eq!(
+            lone.decode(&untrusted).unwrap_err(),
+            RangeCoderError::TooLong {
+                len: u64::MAX,
+                max_len: 100
+            }
+        );
+        let lone_text = ['a'; 100];
+        assert_eq!(
+            lone.decode(&lone.encode(&lone_text).unwrap()).unwrap(),
+            lone_text
+        );
+        assert_eq!(
+            lone.encode(&['a'; 101]).unwrap_err(),
+            RangeCoderError::TooLong {
+                len: 101,
+                max_len: 100
+            }
+        );
+    }
+}
@@ -0,0 +1,59 @@
+use std::fmt::Debug;
+
+use entropy_coder::EntropyCoder;
+use huffman::{create_byte_huffman_encoding, create_huffman_encoding};
+use range_coder::{AdaptiveModel, AdaptiveRangeCoder, StaticModel, StaticRangeCoder};
+
+#[cfg(test)]
+mod bench_util;
+mod entropy_coder;
+mod huffman;
+mod range_coder;
+
+fn compare<S: PartialEq + Debug, C: EntropyCoder<S>>(name: &str, coder: &C, symbols: &[S]) {
+    let encoded = coder.encode(symbols).unwrap();
+    assert_eq!(coder.decode(&encoded).unwrap(), symbols);
+    println!(
+        "{name:>16}: {:>8} bits, {:.4} bits/symbol",
+        encoded.bit_len(),
+        coder.rate(symbols).unwrap()
+    );
+}
+
+fn main() {
+    let text = "This is test data for generating a huffman encoding!";
+    let symbols = text.chars().collect::<Vec<_>>();
+    println!("{text:?}");
+    compare("huffman", &create_huffman_encoding(text).unwrap(), &symbols);
+    compare(
+        "static range",
+        &StaticRangeCoder::new(StaticModel::from_symbols(text.chars())),
+        &symbols,
+    );
+    compare(
+        "adaptive range",
+        &AdaptiveRangeCoder::new(AdaptiveModel::new(text.chars())),
+        &symbols,
+    );
+
+    let data = (0..100_000u32)
+        .map(|i| if i % 17 == 0 { (i % 7) as u8 + 1 } else { 0 })
+        .collect::<Vec<_>>();
+    println!();
+    println!("{} mostly zero bytes", data.len());
+    compare(
+        "huffman",
+        &create_byte_huffman_encoding(&data).unwrap(),
+        &data,
+    );
+    compare(
+        "static range",
+        &StaticRangeCoder::new(StaticModel::from_symbols(data.iter().copied())),
+        &data,
+    );
+    compare(
+        "adaptive range",
+        &AdaptiveRangeCoder::new(AdaptiveModel::bytes()),
+        &data,
+    );
+}
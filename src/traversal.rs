@@ -0,0 +1,447 @@
+use std::collections::{HashMap, HashSet, VecDeque};
+
+use crate::graph::WeightedGraph;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) struct Visit<'graph> {
+    pub(crate) node: &'graph str,
+    pub(crate) depth: usize,
+    pub(crate) parent: Option<&'graph str>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum EdgeKind {
+    Tree,
+    Back,
+    Forward,
+    Cross,
+}
+
+type NoVisitor<'graph> = fn(&'graph str, &'graph str, EdgeKind);
+
+// Graphs may only list nodes with outgoing edges, like `network_flow`'s do, so
+// edge targets are searched too. Unknown nodes give empty traversals.
+fn find_node<'graph, G: WeightedGraph>(graph: &'graph G, name: &str) -> Option<&'graph str> {
+    graph
+        .nodes()
+        .chain(graph.edges().map(|(_, to, _)| to))
+        .find(|node| *node == name)
+}
+
+// Neighbors are visited in name order so that traversals are reproducible
+// despite the graphs being backed by hash maps.
+fn sorted_neighbors<'graph, G: WeightedGraph>(graph: &'graph G, node: &str) -> Vec<&'graph str> {
+    let mut neighbors = graph
+        .neighbors(node)
+        .map(|(neighbor, _)| neighbor)
+        .collect::<Vec<_>>();
+    neighbors.sort_unstable();
+    neighbors
+}
+
+pub(crate) struct Bfs<'graph, G, F = NoVisitor<'graph>> {
+    graph: &'graph G,
+    queue: VecDeque<Visit<'graph>>,
+    parents: HashMap<&'graph str, Option<&'graph str>>,
+    visitor: F,
+}
+
+impl<'graph, G: WeightedGraph> Bfs<'graph, G> {
+    pub(crate) fn new(graph: &'graph G, start_node: &str) -> Self {
+        let start_node = find_node(graph, start_node);
+        Self {
+            graph,
+            queue: start_node
+                .map(|node| Visit {
+                    node,
+                    depth: 0,
+                    parent: None,
+                })
+                .into_iter()
+                .collect(),
+            parents: start_node.map(|node| (node, None)).into_iter().collect(),
+            visitor: |_, _, _| {},
+        }
+    }
+}
+
+impl<'graph, G, F> Bfs<'graph, G, F> {
+    pub(crate) fn with_visitor<V>(self, visitor: V) -> Bfs<'graph, G, V>
+    where
+        V: FnMut(&'graph str, &'graph str, EdgeKind),
+    {
+        Bfs {
+            graph: self.graph,
+            queue: self.queue,
+            parents: self.parents,
+            visitor,
+        }
+    }
+
+    fn is_ancestor(&self, ancestor: &str, mut node: &'graph str) -> bool {
+        loop {
+            if node == ancestor {
+                return true;
+            }
+            match self.parents[node] {
+                Some(parent) => node = parent,
+                None => return false,
+            }
+        }
+    }
+}
+
+impl<'graph, G, F> Iterator for Bfs<'graph, G, F>
+where
+    G: WeightedGraph,
+    F: FnMut(&'graph str, &'graph str, EdgeKind),
+{
+    type Item = Visit<'graph>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let visit = self.queue.pop_front()?;
+        for neighbor in sorted_neighbors(self.graph, visit.node) {
+            if self.parents.contains_key(neighbor) {
+                // Breadth first search never produces forward edges.
+                let kind = if self.is_ancestor(neighbor, visit.node) {
+                    EdgeKind::Back
+                } else {
+                    EdgeKind::Cross
+                };
+                (self.visitor)(visit.node, neighbor, kind);
+                continue;
+            }
+            (self.visitor)(visit.node, neighbor, EdgeKind::Tree);
+            self.parents.insert(neighbor, Some(visit.node));
+            self.queue.push_back(Visit {
+                node: neighbor,
+                depth: visit.depth + 1,
+                parent: Some(visit.node),
+            });
+        }
+        Some(visit)
+    }
+}
+
+struct Frame<'graph> {
+    visit: Visit<'graph>,
+    neighbors: Vec<&'graph str>,
+    next_neighbor: usize,
+}
+
+enum DfsEvent<'graph> {
+    Discover(Visit<'graph>),
+    Finish(Visit<'graph>),
+}
+
+// Shared state machine behind `Dfs` and `DfsPostOrder`. Each call to `step`
+// advances the search until a node is either discovered or finished.
+struct DfsState<'graph, G, F> {
+    graph: &'graph G,
+    start_node: Option<&'graph str>,
+    stack: Vec<Frame<'graph>>,
+    discovered: HashMap<&'graph str, usize>,
+    finished: HashSet<&'graph str>,
+    visitor: F,
+}
+
+impl<'graph, G: WeightedGraph> DfsState<'graph, G, NoVisitor<'graph>> {
+    fn new(graph: &'graph G, start_node: &str) -> Self {
+        Self {
+            graph,
+            start_node: find_node(graph, start_node),
+            stack: Vec::new(),
+            discovered: HashMap::new(),
+            finished: HashSet::new(),
+            visitor: |_, _, _| {},
+        }
+    }
+}
+
+impl<'graph, G, F> DfsState<'graph, G, F>
+where
+    G: WeightedGraph,
+    F: FnMut(&'graph str, &'graph str, EdgeKind),
+{
+    fn with_visitor<V>(self, visitor: V) -> DfsState<'graph, G, V> {
+        DfsState {
+            graph: self.graph,
+            start_node: self.start_node,
+            stack: self.stack,
+            discovered: self.discovered,
+            finished: self.finished,
+            visitor,
+        }
+    }
+
+    fn discover(&mut self, visit: Visit<'graph>) -> DfsEvent<'graph> {
+        self.discovered.insert(visit.node, self.discovered.len());
+        self.stack.push(Frame {
+            visit,
+            neighbors: sorted_neighbors(self.graph, visit.node),
+            next_neighbor: 0,
+        });
+        DfsEvent::Discover(visit)
+    }
+
+    fn step(&mut self) -> Option<DfsEvent<'graph>> {
+        if let Some(start_node) = self.start_node.take() {
+            return Some(self.discover(Visit {
+                node: start_node,
+                depth: 0,
+                parent: None,
+            }));
+        }
+        loop {
+            let frame = self.stack.last_mut()?;
+            let Some(&neighbor) = frame.neighbors.get(frame.next_neighbor) else {
+                let visit = self.stack.pop().unwrap().visit;
+                self.finished.insert(visit.node);
+                return Some(DfsEvent::Finish(visit));
+            };
+            frame.next_neighbor += 1;
+            let visit = frame.visit;
+            let kind = match self.discovered.get(neighbor) {
+                None => EdgeKind::Tree,
+                Some(_) if !self.finished.contains(neighbor) => EdgeKind::Back,
+                Some(order) if *order > self.discovered[visit.node] => EdgeKind::Forward,
+                Some(_) => EdgeKind::Cross,
+            };
+            (self.visitor)(visit.node, neighbor, kind);
+            if kind == EdgeKind::Tree {
+                return Some(self.discover(Visit {
+                    node: neighbor,
+                    depth: visit.depth + 1,
+                    parent: Some(visit.node),
+                }));
+            }
+        }
+    }
+}
+
+pub(crate) struct Dfs<'graph, G, F = NoVisitor<'graph>> {
+    state: DfsState<'graph, G, F>,
+}
+
+impl<'graph, G: WeightedGraph> Dfs<'graph, G> {
+    pub(crate) fn new(graph: &'graph G, start_node: &str) -> Self {
+        Self {
+            state: DfsState::new(graph, start_node),
+        }
+    }
+}
+
+impl<'graph, G, F> Dfs<'graph, G, F>
+where
+    G: WeightedGraph,
+    F: FnMut(&'graph str, &'graph str, EdgeKind),
+{
+    pub(crate) fn with_visitor<V>(self, visitor: V) -> Dfs<'graph, G, V>
+    where
+        V: FnMut(&'graph str, &'graph str, EdgeKind),
+    {
+        Dfs {
+            state: self.state.with_visitor(visitor),
+        }
+    }
+}
+
+impl<'graph, G, F> Iterator for Dfs<'graph, G, F>
+where
+    G: WeightedGraph,
+    F: FnMut(&'graph str, &'graph str, EdgeKind),
+{
+    type Item = Visit<'graph>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            if let DfsEvent::Discover(visit) = self.state.step()? {
+                return Some(visit);
+            }
+        }
+    }
+}
+
+pub(crate) struct DfsPostOrder<'graph, G, F = NoVisitor<'graph>> {
+    state: DfsState<'graph, G, F>,
+}
+
+impl<'graph, G: WeightedGraph> DfsPostOrder<'graph, G> {
+    pub(crate) fn new(graph: &'graph G, start_node: &str) -> Self {
+        Self {
+            state: DfsState::new(graph, start_node),
+        }
+    }
+}
+
+impl<'graph, G, F> DfsPostOrder<'graph, G, F>
+where
+    G: WeightedGraph,
+    F: FnMut(&'graph str, &'graph str, EdgeKind),
+{
+    pub(crate) fn with_visitor<V>(self, visitor: V) -> DfsPostOrder<'graph, G, V>
+    where
+        V: FnMut(&'graph str, &'graph str, EdgeKind),
+    {
+        DfsPostOrder {
+            state: self.state.with_visitor(visitor),
+        }
+    }
+}
+
+impl<'graph, G, F> Iterator for DfsPostOrder<'graph, G, F>
+where
+    G: WeightedGraph,
+    F: FnMut(&'graph str, &'graph str, EdgeKind),
+{
+    type Item = Visit<'graph>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            if let DfsEvent::Finish(visit) = self.state.step()? {
+                return Some(visit);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::dijkstra::Graph;
+    use crate::network_flow::NetworkGraph;
+
+    fn construct_graph() -> Graph {
+        let mut graph = Graph::new();
+        graph.add_edge("a", "b", 1);
+        graph.add_edge("a", "c", 1);
+        graph.add_edge("a", "d", 1);
+        graph.add_edge("b", "d", 1);
+        graph.add_edge("c", "b", 1);
+        graph.add_edge("d", "a", 1);
+        graph.add_node("e");
+        graph
+    }
+
+    fn names<'graph>(visits: impl Iterator<Item = Visit<'graph>>) -> Vec<&'graph str> {
+        visits.map(|visit| visit.node).collect()
+    }
+
+    #[test]
+    fn bfs_yields_depth_and_parent() {
+        let graph = construct_graph();
+        let visits = Bfs::new(&graph, "a").collect::<Vec<_>>();
+        assert_eq!(
+            visits,
+            vec![
+                Visit {
+                    node: "a",
+                    depth: 0,
+                    parent: None
+                },
+                Visit {
+                    node: "b",
+                    depth: 1,
+                    parent: Some("a")
+                },
+                Visit {
+                    node: "c",
+                    depth: 1,
+                    parent: Some("a")
+                },
+                Visit {
+                    node: "d",
+                    depth: 1,
+                    parent: Some("a")
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn dfs_orders() {
+        let graph = construct_graph();
+        assert_eq!(names(Dfs::new(&graph, "a")), vec!["a", "b", "d", "c"]);
+        assert_eq!(
+            names(DfsPostOrder::new(&graph, "a")),
+            vec!["d", "b", "c", "a"]
+        );
+        assert_eq!(names(Dfs::new(&graph, "e")), vec!["e"]);
+    }
+
+    #[test]
+    fn visitor_classifies_edges() {
+        let graph = construct_graph();
+        let mut edges = Vec::new();
+        Dfs::new(&graph, "a")
+            .with_visitor(|from, to, kind| edges.push((from, to, kind)))
+            .for_each(drop);
+        assert_eq!(
+            edges,
+            vec![
+                ("a", "b", EdgeKind::Tree),
+                ("b", "d", EdgeKind::Tree),
+                ("d", "a", EdgeKind::Back),
+                ("a", "c", EdgeKind::Tree),
+                ("c", "b", EdgeKind::Cross),
+                ("a", "d", EdgeKind::Forward),
+            ]
+        );
+
+        let mut edges = Vec::new();
+        Bfs::new(&graph, "a")
+            .with_visitor(|from, to, kind| edges.push((from, to, kind)))
+            .for_each(drop);
+        assert_eq!(
+            edges,
+            vec![
+                ("a", "b", EdgeKind::Tree),
+                ("a", "c", EdgeKind::Tree),
+                ("a", "d", EdgeKind::Tree),
+                ("b", "d", EdgeKind::Cross),
+                ("c", "b", EdgeKind::Cross),
+                ("d", "a", EdgeKind::Back),
+            ]
+        );
+    }
+
+    #[test]
+    fn stops_early_on_network_graph() {
+        let mut graph = NetworkGraph::new();
+        for i in 0..100 {
+            graph.add_edge(&i.to_string(), &(i + 1).to_string(), 1);
+        }
+        let mut tree_edges = 0;
+        let found = Dfs::new(&graph, "0")
+            .with_visitor(|_, _, kind| {
+                if kind == EdgeKind::Tree {
+                    tree_edges += 1;
+                }
+            })
+            .find(|visit| visit.node == "10")
+            .unwrap();
+        assert_eq!(found.depth, 10);
+        assert_eq!(found.parent, Some("9"));
+        assert_eq!(tree_edges, 10);
+    }
+
+    #[test]
+    fn starts_at_sinks_and_unknown_nodes() {
+        let mut graph = NetworkGraph::new();
+        graph.add_edge("s", "t", 1);
+        let sink = Visit {
+            node: "t",
+            depth: 0,
+            parent: None,
+        };
+        assert_eq!(Bfs::new(&graph, "t").collect::<Vec<_>>(), vec![sink]);
+        assert_eq!(Dfs::new(&graph, "t").collect::<Vec<_>>(), vec![sink]);
+        assert_eq!(
+            DfsPostOrder::new(&graph, "t").collect::<Vec<_>>(),
+            vec![sink]
+        );
+        assert_eq!(Bfs::new(&graph, "x").count(), 0);
+        assert_eq!(Dfs::new(&graph, "x").count(), 0);
+        assert_eq!(DfsPostOrder::new(&graph, "x").count(), 0);
+    }
+}
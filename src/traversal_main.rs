@@ -0,0 +1,50 @@
+mod dijkstra;
+mod graph;
+mod network_flow;
+mod traversal;
+
+use dijkstra::Graph;
+use traversal::{Bfs, Dfs, DfsPostOrder};
+
+fn construct_graph() -> Graph {
+    let mut graph = Graph::new();
+    graph.add_edge("a", "b", 2);
+    graph.add_edge("a", "e", 3);
+    graph.add_edge("b", "c", 2);
+    graph.add_edge("c", "h", 4);
+    graph.add_edge("d", "a", 1);
+    graph.add_edge("e", "d", 4);
+    graph.add_edge("e", "f", 3);
+    graph.add_edge("f", "c", 2);
+    graph.add_edge("f", "g", 4);
+    graph.add_edge("g", "h", 1);
+    graph.add_node("h");
+    graph
+}
+
+fn main() {
+    let graph = construct_graph();
+
+    println!("Breadth first:");
+    for visit in Bfs::new(&graph, "a") {
+        println!("{:?}", visit);
+    }
+    println!();
+
+    println!("Depth first:");
+    let dfs = Dfs::new(&graph, "a").with_visitor(|from, to, kind| {
+        println!("  {from} -> {to}: {kind:?}");
+    });
+    for visit in dfs {
+        println!("{:?}", visit);
+    }
+    println!();
+
+    println!("Depth first, post order, stopping at f:");
+    for visit in DfsPostOrder::new(&graph, "a") {
+        println!("{:?}", visit);
+        if visit.node == "f" {
+            break;
+        }
+    }
+}
@@ -1,6 +1,109 @@
-use std::fmt::{Debug, Formatter, Result as FmtResult};
+use std::borrow::Borrow;
+use std::collections::HashMap;
+use std::error::Error;
+use std::fmt::{Debug, Display, Formatter, Result as FmtResult};
+use std::hash::Hash;
+use std::marker::PhantomData;
 
-fn debug_union_find(nodes: &[isize], starting_index: isize, f: &mut Formatter<'_>) -> FmtResult {
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub(crate) enum UnionFindError {
+    OutOfBounds {
+        index: usize,
+        starting_index: usize,
+        len: usize,
+    },
+    IndexOverflow {
+        starting_index: usize,
+        len: usize,
+    },
+    ParentOutOfRange {
+        node: usize,
+        parent: isize,
+    },
+    Cycle {
+        node: usize,
+    },
+    SizeMismatch {
+        root: usize,
+        recorded: usize,
+        actual: usize,
+    },
+    RankMismatch {
+        root: usize,
+        rank: usize,
+        height: usize,
+        size: usize,
+    },
+    InconsistentDiff {
+        a: usize,
+        b: usize,
+        requested: i64,
+        existing: i64,
+    },
+}
+
+impl Display for UnionFindError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> FmtResult {
+        match self {
+            UnionFindError::OutOfBounds {
+                index,
+                starting_index,
+                len,
+            } => write!(
+                f,
+                "index {index} is out of bounds, expected {starting_index}..{}",
+                starting_index.saturating_add(*len)
+            ),
+            UnionFindError::IndexOverflow {
+                starting_index,
+                len,
+            } => write!(
+                f,
+                "{len} elements starting at {starting_index} do not fit in an isize"
+            ),
+            UnionFindError::ParentOutOfRange { node, parent } => {
+                write!(f, "node {node} has out of range parent {parent}")
+            }
+            UnionFindError::Cycle { node } => {
+                write!(f, "node {node} is part of a cycle and has no root")
+            }
+            UnionFindError::SizeMismatch {
+                root,
+                recorded,
+                actual,
+            } => write!(
+                f,
+                "root {root} records a size of {recorded} but its set has {actual} members"
+            ),
+            UnionFindError::RankMismatch {
+                root,
+                rank,
+                height,
+                size,
+            } => write!(
+                f,
+                "root {root} records rank {rank} which is impossible for a tree of height {height} with {size} members"
+            ),
+            UnionFindError::InconsistentDiff {
+                a,
+                b,
+                requested,
+                existing,
+            } => write!(
+                f,
+                "cannot set {a} - {b} to {requested}, it is already constrained to {existing}"
+            ),
+        }
+    }
+}
+
+impl Error for UnionFindError {}
+
+pub(crate) fn debug_union_find(
+    nodes: &[isize],
+    starting_index: isize,
+    f: &mut Formatter<'_>,
+) -> FmtResult {
     f.debug_list()
         .entries(
             nodes
@@ -41,103 +144,417 @@ impl<'a> Iterator for Roots<'a> {
     }
 }
 
+pub(crate) fn find_root(nodes: &[isize], mut x: usize) -> usize {
+    while nodes[x] >= 0 {
+        x = nodes[x] as usize;
+    }
+    x
+}
+
+pub(crate) struct Members<'a> {
+    buffer: &'a [isize],
+    starting_index: usize,
+    root: usize,
+    index: usize,
+}
+
+impl<'a> Members<'a> {
+    pub(crate) fn new(buffer: &'a [isize], starting_index: usize, root: usize) -> Self {
+        Self {
+            buffer,
+            starting_index,
+            root: root - starting_index,
+            index: 0,
+        }
+    }
+}
+
+impl<'a> Iterator for Members<'a> {
+    type Item = usize;
+    fn next(&mut self) -> Option<Self::Item> {
+        while self.index < self.buffer.len() {
+            let index = self.index;
+            self.index += 1;
+            if find_root(self.buffer, index) == self.root {
+                return Some(index + self.starting_index);
+            }
+        }
+        None
+    }
+}
+
 pub(crate) trait UnionFind {
     fn new(n: usize, starting_index: usize) -> Self;
     fn find(&mut self, x: usize) -> usize;
     fn union(&mut self, root_1: usize, root_2: usize);
-    fn roots(&self) -> Roots;
+    fn roots(&self) -> Roots<'_>;
     fn from_nodes_and_starting_index(nodes: Box<[isize]>, starting_index: usize) -> Self;
+    fn find_immutable(&self, x: usize) -> usize;
+    // Yields every element in the same set as `x`, which need not be a root.
+    fn members(&self, x: usize) -> Members<'_>;
+    fn same_set(&self, a: usize, b: usize) -> bool {
+        self.find_immutable(a) == self.find_immutable(b)
+    }
+    fn set_size(&self, x: usize) -> usize {
+        self.members(x).count()
+    }
+    fn num_sets(&self) -> usize {
+        self.roots().count()
+    }
+    fn try_find(&mut self, x: usize) -> Result<usize, UnionFindError>;
+    fn try_union(&mut self, root_1: usize, root_2: usize) -> Result<(), UnionFindError>;
+    fn try_from_nodes_and_starting_index(
+        nodes: Box<[isize]>,
+        starting_index: usize,
+    ) -> Result<Self, UnionFindError>
+    where
+        Self: Sized;
+    fn make_set(&mut self) -> usize;
+    fn reserve(&mut self, additional: usize);
+    fn len(&self) -> usize;
+    fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+    fn starting_index(&self) -> usize;
+    // The raw parent array as accepted by `from_nodes_and_starting_index`.
+    fn nodes(&self) -> &[isize];
+    // Dense labels in 0..num_sets(), numbered in order of each set's smallest
+    // element. The label of element x is at index x - starting_index().
+    fn component_labels(&mut self) -> Vec<usize> {
+        const UNLABELLED: usize = usize::MAX;
+        let starting_index = self.starting_index();
+        let mut root_labels = vec![UNLABELLED; self.len()];
+        let mut labels = Vec::with_capacity(self.len());
+        let mut next_label = 0;
+        for x in 0..self.len() {
+            let root = self.find(x + starting_index) - starting_index;
+            if root_labels[root] == UNLABELLED {
+                root_labels[root] = next_label;
+                next_label += 1;
+            }
+            labels.push(root_labels[root]);
+        }
+        labels
+    }
+    // The members of every set in ascending order, with sets ordered by their
+    // smallest element.
+    fn components(&mut self) -> Vec<Vec<usize>> {
+        let starting_index = self.starting_index();
+        let mut components: Vec<Vec<usize>> = Vec::new();
+        for (x, label) in self.component_labels().into_iter().enumerate() {
+            if label == components.len() {
+                components.push(Vec::new());
+            }
+            components[label].push(x + starting_index);
+        }
+        components
+    }
 }
 
-#[derive(PartialEq, Eq, Default)]
-pub(crate) struct UnionFindBySize {
-    nodes: Box<[isize]>,
+pub(crate) fn check_bounds(
+    x: usize,
     starting_index: usize,
+    len: usize,
+) -> Result<(), UnionFindError> {
+    if x >= starting_index && x - starting_index < len {
+        Ok(())
+    } else {
+        Err(UnionFindError::OutOfBounds {
+            index: x,
+            starting_index,
+            len,
+        })
+    }
 }
 
-impl Debug for UnionFindBySize {
-    fn fmt(&self, f: &mut Formatter<'_>) -> FmtResult {
-        debug_union_find(&self.nodes, self.starting_index as isize, f)
+// Parents are stored as isize, so every element has to fit in one.
+pub(crate) fn check_index_range(starting_index: usize, len: usize) -> Result<(), UnionFindError> {
+    match starting_index.checked_add(len) {
+        Some(end) if end <= isize::MAX as usize => Ok(()),
+        _ => Err(UnionFindError::IndexOverflow {
+            starting_index,
+            len,
+        }),
     }
 }
 
-impl UnionFind for UnionFindBySize {
-    fn new(n: usize, starting_index: usize) -> Self {
-        Self {
-            nodes: vec![-1; n].into_boxed_slice(),
-            starting_index,
+pub(crate) struct TreeShape {
+    pub(crate) size: usize,
+    pub(crate) height: usize,
+}
+
+// Checks that every parent is in range and that following parents from any
+// node reaches a root, returning the size and height of every root's tree.
+pub(crate) fn validate_nodes(
+    nodes: &[isize],
+    starting_index: usize,
+) -> Result<Vec<(usize, TreeShape)>, UnionFindError> {
+    check_index_range(starting_index, nodes.len())?;
+    for (node, parent) in nodes.iter().copied().enumerate() {
+        if parent >= nodes.len() as isize {
+            return Err(UnionFindError::ParentOutOfRange {
+                node: node + starting_index,
+                parent: parent.saturating_add_unsigned(starting_index),
+            });
         }
     }
-    fn find(&mut self, mut x: usize) -> usize {
-        assert!(
-            x >= self.starting_index && x - self.starting_index < self.nodes.len(),
-            "out of bounds"
-        );
-        x -= self.starting_index;
-        while self.nodes[x] >= 0 {
-            x = self.nodes[x] as usize;
+
+    const UNVISITED: usize = usize::MAX;
+    const IN_PROGRESS: usize = usize::MAX - 1;
+    let mut depths = vec![UNVISITED; nodes.len()];
+    // Filled in along with the depths, so each node's root is found once.
+    let mut roots = vec![0; nodes.len()];
+    let mut path = Vec::new();
+    for start in 0..nodes.len() {
+        let mut x = start;
+        let mut depth = loop {
+            if depths[x] == IN_PROGRESS {
+                return Err(UnionFindError::Cycle {
+                    node: x + starting_index,
+                });
+            }
+            if depths[x] != UNVISITED {
+                break depths[x];
+            }
+            if nodes[x] < 0 {
+                depths[x] = 0;
+                roots[x] = x;
+                break 0;
+            }
+            depths[x] = IN_PROGRESS;
+            path.push(x);
+            x = nodes[x] as usize;
+        };
+        let root = roots[x];
+        while let Some(x) = path.pop() {
+            depth += 1;
+            depths[x] = depth;
+            roots[x] = root;
         }
-        x + self.starting_index
     }
-    fn union(&mut self, root_1: usize, root_2: usize) {
-        assert!(
-            root_1 >= self.starting_index && root_1 - self.starting_index < self.nodes.len(),
-            "out of bounds"
-        );
-        assert!(
-            root_2 >= self.starting_index && root_2 - self.starting_index < self.nodes.len(),
-            "out of bounds"
-        );
-        let root_1 = self.find(root_1) - self.starting_index;
-        let root_2 = self.find(root_2) - self.starting_index;
-        let (bigger, smaller) = if self.nodes[root_2] < self.nodes[root_1] {
+
+    let mut shapes = nodes
+        .iter()
+        .enumerate()
+        .filter(|(_, parent)| **parent < 0)
+        .map(|(root, _)| (root, TreeShape { size: 0, height: 0 }))
+        .collect::<HashMap<_, _>>();
+    for (root, depth) in roots.into_iter().zip(depths) {
+        let shape = shapes.get_mut(&root).unwrap();
+        shape.size += 1;
+        shape.height = shape.height.max(depth);
+    }
+    let mut shapes = shapes.into_iter().collect::<Vec<_>>();
+    shapes.sort_unstable_by_key(|(root, _)| *root);
+    Ok(shapes)
+}
+
+pub(crate) trait Linking {
+    fn link(nodes: &mut [isize], root_1: usize, root_2: usize);
+    // Returns `None` when the root does not record the size of its set.
+    fn set_size(nodes: &[isize], root: usize) -> Option<usize>;
+    fn validate_root(
+        root: usize,
+        recorded: isize,
+        size: usize,
+        height: usize,
+    ) -> Result<(), UnionFindError>;
+}
+
+// Roots store their negated set size.
+#[derive(PartialEq, Eq, Default, Debug)]
+pub(crate) struct BySize;
+
+impl Linking for BySize {
+    fn link(nodes: &mut [isize], root_1: usize, root_2: usize) {
+        let (bigger, smaller) = if nodes[root_2] < nodes[root_1] {
             (root_2, root_1)
         } else {
             (root_1, root_2)
         };
-        self.nodes[bigger] += self.nodes[smaller];
-        self.nodes[smaller] = bigger as isize;
+        nodes[bigger] += nodes[smaller];
+        nodes[smaller] = bigger as isize;
     }
-    fn roots(&self) -> Roots {
-        Roots::new(&self.nodes, self.starting_index)
+    fn set_size(nodes: &[isize], root: usize) -> Option<usize> {
+        Some(-nodes[root] as usize)
     }
-    fn from_nodes_and_starting_index(nodes: Box<[isize]>, starting_index: usize) -> Self {
-        Self {
-            nodes,
-            starting_index,
+    fn validate_root(
+        root: usize,
+        recorded: isize,
+        size: usize,
+        _height: usize,
+    ) -> Result<(), UnionFindError> {
+        // `unsigned_abs` because a malformed root may hold `isize::MIN`.
+        if recorded.unsigned_abs() == size {
+            Ok(())
+        } else {
+            Err(UnionFindError::SizeMismatch {
+                root,
+                recorded: recorded.unsigned_abs(),
+                actual: size,
+            })
+        }
+    }
+}
+
+// Roots store their negated rank plus one. Without compression the rank is
+// exactly the height of the tree.
+#[derive(PartialEq, Eq, Default, Debug)]
+pub(crate) struct ByRank;
+
+impl Linking for ByRank {
+    fn link(nodes: &mut [isize], root_1: usize, root_2: usize) {
+        let (bigger, smaller) = if nodes[root_2] < nodes[root_1] {
+            (root_2, root_1)
+        } else {
+            (root_1, root_2)
+        };
+        if nodes[bigger] == nodes[smaller] {
+            nodes[bigger] -= 1;
+        }
+        nodes[smaller] = bigger as isize;
+    }
+    fn set_size(_nodes: &[isize], _root: usize) -> Option<usize> {
+        None
+    }
+    // Compression can only make trees shallower than their rank, and a tree of
+    // rank r always has at least 2^r members.
+    fn validate_root(
+        root: usize,
+        recorded: isize,
+        size: usize,
+        height: usize,
+    ) -> Result<(), UnionFindError> {
+        let rank = recorded.unsigned_abs() - 1;
+        if rank >= height && rank < usize::BITS as usize && size >= 1 << rank {
+            Ok(())
+        } else {
+            Err(UnionFindError::RankMismatch {
+                root,
+                rank,
+                height,
+                size,
+            })
+        }
+    }
+}
+
+pub(crate) trait Compression {
+    fn find(nodes: &mut [isize], x: usize) -> usize;
+}
+
+#[derive(PartialEq, Eq, Default, Debug)]
+pub(crate) struct NoCompression;
+
+impl Compression for NoCompression {
+    fn find(nodes: &mut [isize], x: usize) -> usize {
+        find_root(nodes, x)
+    }
+}
+
+// Points every node on the path directly at the root. Done in two passes so
+// that long chains cannot overflow the stack.
+#[derive(PartialEq, Eq, Default, Debug)]
+pub(crate) struct FullCompression;
+
+impl Compression for FullCompression {
+    fn find(nodes: &mut [isize], mut x: usize) -> usize {
+        let root = find_root(nodes, x);
+        while nodes[x] >= 0 {
+            let parent = nodes[x] as usize;
+            nodes[x] = root as isize;
+            x = parent;
+        }
+        root
+    }
+}
+
+// Points every other node on the path at its grandparent.
+#[derive(PartialEq, Eq, Default, Debug)]
+pub(crate) struct PathHalving;
+
+impl Compression for PathHalving {
+    fn find(nodes: &mut [isize], mut x: usize) -> usize {
+        loop {
+            let parent = nodes[x];
+            if parent < 0 {
+                return x;
+            }
+            let grandparent = nodes[parent as usize];
+            if grandparent < 0 {
+                return parent as usize;
+            }
+            nodes[x] = grandparent;
+            x = grandparent as usize;
+        }
+    }
+}
+
+// Points every node on the path at its grandparent.
+#[derive(PartialEq, Eq, Default, Debug)]
+pub(crate) struct PathSplitting;
+
+impl Compression for PathSplitting {
+    fn find(nodes: &mut [isize], mut x: usize) -> usize {
+        loop {
+            let parent = nodes[x];
+            if parent < 0 {
+                return x;
+            }
+            let grandparent = nodes[parent as usize];
+            if grandparent < 0 {
+                return parent as usize;
+            }
+            nodes[x] = grandparent;
+            x = parent as usize;
         }
     }
 }
 
 #[derive(PartialEq, Eq, Default)]
-pub(crate) struct UnionFindByHeight {
-    nodes: Box<[isize]>,
+pub(crate) struct GenericUnionFind<L, C> {
+    nodes: Vec<isize>,
     starting_index: usize,
+    strategy: PhantomData<(L, C)>,
 }
 
-impl Debug for UnionFindByHeight {
+pub(crate) type UnionFindBySize = GenericUnionFind<BySize, NoCompression>;
+pub(crate) type UnionFindByHeight = GenericUnionFind<ByRank, NoCompression>;
+pub(crate) type UnionFindBySizeWithCompression = GenericUnionFind<BySize, FullCompression>;
+
+impl<L, C> Debug for GenericUnionFind<L, C> {
     fn fmt(&self, f: &mut Formatter<'_>) -> FmtResult {
         debug_union_find(&self.nodes, self.starting_index as isize, f)
     }
 }
 
-impl UnionFind for UnionFindByHeight {
+impl<L, C> GenericUnionFind<L, C> {
+    fn check_bounds(&self, x: usize) -> Result<(), UnionFindError> {
+        check_bounds(x, self.starting_index, self.nodes.len())
+    }
+}
+
+impl<L: Linking, C: Compression> UnionFind for GenericUnionFind<L, C> {
     fn new(n: usize, starting_index: usize) -> Self {
         Self {
-            nodes: vec![-1; n].into_boxed_slice(),
+            nodes: vec![-1; n],
             starting_index,
+            strategy: PhantomData,
         }
     }
-    fn find(&mut self, mut x: usize) -> usize {
+    fn find(&mut self, x: usize) -> usize {
         assert!(
             x >= self.starting_index && x - self.starting_index < self.nodes.len(),
             "out of bounds"
         );
-        x -= self.starting_index;
-        while self.nodes[x] >= 0 {
-            x = self.nodes[x] as usize;
-        }
-        x + self.starting_index
+        C::find(&mut self.nodes, x - self.starting_index) + self.starting_index
+    }
+    fn find_immutable(&self, x: usize) -> usize {
+        assert!(
+            x >= self.starting_index && x - self.starting_index < self.nodes.len(),
+            "out of bounds"
+        );
+        find_root(&self.nodes, x - self.starting_index) + self.starting_index
     }
     fn union(&mut self, root_1: usize, root_2: usize) {
         assert!(
@@ -150,88 +567,599 @@ impl UnionFind for UnionFindByHeight {
         );
         let root_1 = self.find(root_1) - self.starting_index;
         let root_2 = self.find(root_2) - self.starting_index;
-        let (bigger, smaller) = if self.nodes[root_2] < self.nodes[root_1] {
-            (root_2, root_1)
-        } else {
-            (root_1, root_2)
-        };
-        if self.nodes[bigger] == self.nodes[smaller] {
-            self.nodes[bigger] -= 1;
+        if root_1 == root_2 {
+            return;
         }
-        self.nodes[smaller] = bigger as isize;
+        L::link(&mut self.nodes, root_1, root_2);
     }
-    fn roots(&self) -> Roots {
+    fn roots(&self) -> Roots<'_> {
         Roots::new(&self.nodes, self.starting_index)
     }
     fn from_nodes_and_starting_index(nodes: Box<[isize]>, starting_index: usize) -> Self {
         Self {
-            nodes,
+            nodes: nodes.into_vec(),
             starting_index,
+            strategy: PhantomData,
+        }
+    }
+    fn members(&self, x: usize) -> Members<'_> {
+        Members::new(&self.nodes, self.starting_index, self.find_immutable(x))
+    }
+    fn try_find(&mut self, x: usize) -> Result<usize, UnionFindError> {
+        self.check_bounds(x)?;
+        Ok(self.find(x))
+    }
+    fn try_union(&mut self, root_1: usize, root_2: usize) -> Result<(), UnionFindError> {
+        self.check_bounds(root_1)?;
+        self.check_bounds(root_2)?;
+        self.union(root_1, root_2);
+        Ok(())
+    }
+    fn try_from_nodes_and_starting_index(
+        nodes: Box<[isize]>,
+        starting_index: usize,
+    ) -> Result<Self, UnionFindError> {
+        for (root, shape) in validate_nodes(&nodes, starting_index)? {
+            L::validate_root(root + starting_index, nodes[root], shape.size, shape.height)?;
         }
+        Ok(Self::from_nodes_and_starting_index(nodes, starting_index))
+    }
+    fn set_size(&self, x: usize) -> usize {
+        let root = self.find_immutable(x);
+        L::set_size(&self.nodes, root - self.starting_index)
+            .unwrap_or_else(|| self.members(root).count())
+    }
+    fn make_set(&mut self) -> usize {
+        self.nodes.push(-1);
+        self.nodes.len() - 1 + self.starting_index
+    }
+    fn reserve(&mut self, additional: usize) {
+        self.nodes.reserve(additional);
+    }
+    fn len(&self) -> usize {
+        self.nodes.len()
+    }
+    fn starting_index(&self) -> usize {
+        self.starting_index
+    }
+    fn nodes(&self) -> &[isize] {
+        &self.nodes
     }
 }
 
+// Union by size that also tracks the difference between the values of nodes in
+// the same set. Each node stores its value minus the value of its parent, so the
+// root always has a potential of zero.
 #[derive(PartialEq, Eq, Default)]
-pub(crate) struct UnionFindBySizeWithCompression {
-    nodes: Box<[isize]>,
+pub(crate) struct WeightedUnionFind {
+    nodes: Vec<isize>,
+    potentials: Vec<i64>,
     starting_index: usize,
 }
 
-impl Debug for UnionFindBySizeWithCompression {
+impl Debug for WeightedUnionFind {
     fn fmt(&self, f: &mut Formatter<'_>) -> FmtResult {
-        debug_union_find(&self.nodes, self.starting_index as isize, f)
+        let nodes = self.nodes.iter().copied().map(|v| {
+            if v < 0 {
+                v
+            } else {
+                v + self.starting_index as isize
+            }
+        });
+        f.debug_list()
+            .entries(nodes.zip(self.potentials.iter()))
+            .finish()
     }
 }
 
-impl UnionFind for UnionFindBySizeWithCompression {
-    fn new(n: usize, starting_index: usize) -> Self {
+impl WeightedUnionFind {
+    pub(crate) fn new(n: usize, starting_index: usize) -> Self {
         Self {
-            nodes: vec![-1; n].into_boxed_slice(),
+            nodes: vec![-1; n],
+            potentials: vec![0; n],
             starting_index,
         }
     }
-    fn find(&mut self, x: usize) -> usize {
+
+    // Returns the root of `x` along with value[x] - value[root].
+    fn find_with_potential(&mut self, x: usize) -> (usize, i64) {
+        let mut path = Vec::new();
+        let mut root = x;
+        while self.nodes[root] >= 0 {
+            path.push(root);
+            root = self.nodes[root] as usize;
+        }
+        // Walking back from the root means every parent already points at the
+        // root and holds its potential relative to it.
+        for node in path.into_iter().rev() {
+            let parent = self.nodes[node] as usize;
+            if parent != root {
+                self.potentials[node] += self.potentials[parent];
+            }
+            self.nodes[node] = root as isize;
+        }
+        (root, self.potentials[x])
+    }
+
+    pub(crate) fn find(&mut self, x: usize) -> usize {
         assert!(
             x >= self.starting_index && x - self.starting_index < self.nodes.len(),
             "out of bounds"
         );
-        fn recurse(nodes: &mut [isize], x: usize) -> usize {
-            if nodes[x] >= 0 {
-                let ret = recurse(nodes, nodes[x] as usize);
-                nodes[x] = ret as isize;
-                ret
+        self.find_with_potential(x - self.starting_index).0 + self.starting_index
+    }
+
+    // Records the constraint value[a] - value[b] = diff.
+    pub(crate) fn union_with_diff(
+        &mut self,
+        a: usize,
+        b: usize,
+        diff: i64,
+    ) -> Result<(), UnionFindError> {
+        check_bounds(a, self.starting_index, self.nodes.len())?;
+        check_bounds(b, self.starting_index, self.nodes.len())?;
+        let (root_a, potential_a) = self.find_with_potential(a - self.starting_index);
+        let (root_b, potential_b) = self.find_with_potential(b - self.starting_index);
+        if root_a == root_b {
+            let existing = potential_a - potential_b;
+            return if existing == diff {
+                Ok(())
             } else {
-                x
-            }
+                Err(UnionFindError::InconsistentDiff {
+                    a,
+                    b,
+                    requested: diff,
+                    existing,
+                })
+            };
+        }
+        // value[root_a] - value[root_b]
+        let root_diff = diff - potential_a + potential_b;
+        if self.nodes[root_b] < self.nodes[root_a] {
+            self.nodes[root_b] += self.nodes[root_a];
+            self.nodes[root_a] = root_b as isize;
+            self.potentials[root_a] = root_diff;
+        } else {
+            self.nodes[root_a] += self.nodes[root_b];
+            self.nodes[root_b] = root_a as isize;
+            self.potentials[root_b] = -root_diff;
         }
-        recurse(&mut self.nodes, x - self.starting_index) + self.starting_index
+        Ok(())
     }
-    fn union(&mut self, root_1: usize, root_2: usize) {
+
+    // Returns value[a] - value[b], or `None` if the two are unrelated.
+    pub(crate) fn diff(&mut self, a: usize, b: usize) -> Option<i64> {
         assert!(
-            root_1 >= self.starting_index && root_1 - self.starting_index < self.nodes.len(),
+            a >= self.starting_index && a - self.starting_index < self.nodes.len(),
             "out of bounds"
         );
         assert!(
-            root_2 >= self.starting_index && root_2 - self.starting_index < self.nodes.len(),
+            b >= self.starting_index && b - self.starting_index < self.nodes.len(),
             "out of bounds"
         );
-        let root_1 = self.find(root_1) - self.starting_index;
-        let root_2 = self.find(root_2) - self.starting_index;
-        let (bigger, smaller) = if self.nodes[root_2] < self.nodes[root_1] {
-            (root_2, root_1)
-        } else {
-            (root_1, root_2)
-        };
-        self.nodes[bigger] += self.nodes[smaller];
-        self.nodes[smaller] = bigger as isize;
+        let (root_a, potential_a) = self.find_with_potential(a - self.starting_index);
+        let (root_b, potential_b) = self.find_with_potential(b - self.starting_index);
+        (root_a == root_b).then_some(potential_a - potential_b)
+    }
+
+    pub(crate) fn same_set(&mut self, a: usize, b: usize) -> bool {
+        self.find(a) == self.find(b)
+    }
+
+    pub(crate) fn set_size(&mut self, x: usize) -> usize {
+        let root = self.find(x) - self.starting_index;
+        -self.nodes[root] as usize
     }
-    fn roots(&self) -> Roots {
+
+    pub(crate) fn roots(&self) -> Roots<'_> {
         Roots::new(&self.nodes, self.starting_index)
     }
-    fn from_nodes_and_starting_index(nodes: Box<[isize]>, starting_index: usize) -> Self {
+}
+
+// Maps arbitrary keys onto the dense indices of an inner union-find, adding
+// new keys as singleton sets the first time they are seen.
+pub(crate) struct KeyedUnionFind<K, UF = UnionFindBySizeWithCompression> {
+    union_find: UF,
+    indices: HashMap<K, usize>,
+    keys: Vec<K>,
+}
+
+impl<K: Debug, UF: Debug> Debug for KeyedUnionFind<K, UF> {
+    fn fmt(&self, f: &mut Formatter<'_>) -> FmtResult {
+        f.debug_struct("KeyedUnionFind")
+            .field("keys", &self.keys)
+            .field("union_find", &self.union_find)
+            .finish()
+    }
+}
+
+impl<K: Hash + Eq + Clone, UF: UnionFind> KeyedUnionFind<K, UF> {
+    pub(crate) fn new() -> Self {
+        Self::with_capacity(0)
+    }
+
+    pub(crate) fn with_capacity(capacity: usize) -> Self {
+        let mut union_find = UF::new(0, 0);
+        union_find.reserve(capacity);
         Self {
-            nodes,
-            starting_index,
+            union_find,
+            indices: HashMap::with_capacity(capacity),
+            keys: Vec::with_capacity(capacity),
         }
     }
+
+    pub(crate) fn insert(&mut self, key: K) -> usize {
+        if let Some(&index) = self.indices.get(&key) {
+            return index;
+        }
+        let index = self.union_find.make_set();
+        self.indices.insert(key.clone(), index);
+        self.keys.push(key);
+        index
+    }
+
+    pub(crate) fn index_of<Q>(&self, key: &Q) -> Option<usize>
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        self.indices.get(key).copied()
+    }
+
+    pub(crate) fn key(&self, index: usize) -> &K {
+        &self.keys[index]
+    }
+
+    pub(crate) fn find<Q>(&mut self, key: &Q) -> Option<&K>
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        let index = self.index_of(key)?;
+        Some(&self.keys[self.union_find.find(index)])
+    }
+
+    pub(crate) fn union(&mut self, a: K, b: K) {
+        let a = self.insert(a);
+        let b = self.insert(b);
+        self.union_find.union(a, b);
+    }
+
+    pub(crate) fn same_set<Q>(&self, a: &Q, b: &Q) -> bool
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        match (self.index_of(a), self.index_of(b)) {
+            (Some(a), Some(b)) => self.union_find.same_set(a, b),
+            _ => false,
+        }
+    }
+
+    pub(crate) fn reserve(&mut self, additional: usize) {
+        self.union_find.reserve(additional);
+        self.indices.reserve(additional);
+        self.keys.reserve(additional);
+    }
+
+    pub(crate) fn len(&self) -> usize {
+        self.keys.len()
+    }
+
+    pub(crate) fn is_empty(&self) -> bool {
+        self.keys.is_empty()
+    }
+
+    pub(crate) fn union_find(&self) -> &UF {
+        &self.union_find
+    }
+
+    pub(crate) fn components(&mut self) -> Vec<Vec<&K>> {
+        self.union_find
+            .components()
+            .into_iter()
+            .map(|component| component.into_iter().map(|x| &self.keys[x]).collect())
+            .collect()
+    }
+}
+
+impl<K: Hash + Eq + Clone, UF: UnionFind> Default for KeyedUnionFind<K, UF> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn chain(n: usize) -> Box<[isize]> {
+        (0..n as isize)
+            .map(|i| if i == 0 { -(n as isize) } else { i - 1 })
+            .collect()
+    }
+
+    fn check_against_by_size<UF: UnionFind>() {
+        let insertions = [
+            (1, 2),
+            (3, 4),
+            (9, 8),
+            (1, 7),
+            (3, 5),
+            (6, 3),
+            (9, 3),
+            (2, 2),
+        ];
+        let mut expected = UnionFindBySize::new(9, 1);
+        let mut uf = UF::new(9, 1);
+        for (a, b) in insertions {
+            expected.union(a, b);
+            uf.union(a, b);
+        }
+        assert_eq!(uf.roots().count(), expected.roots().count());
+        for a in 1..=9 {
+            for b in 1..=9 {
+                assert_eq!(
+                    uf.find(a) == uf.find(b),
+                    expected.find(a) == expected.find(b)
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn strategies_agree() {
+        check_against_by_size::<GenericUnionFind<BySize, NoCompression>>();
+        check_against_by_size::<GenericUnionFind<BySize, FullCompression>>();
+        check_against_by_size::<GenericUnionFind<BySize, PathHalving>>();
+        check_against_by_size::<GenericUnionFind<BySize, PathSplitting>>();
+        check_against_by_size::<GenericUnionFind<ByRank, NoCompression>>();
+        check_against_by_size::<GenericUnionFind<ByRank, FullCompression>>();
+        check_against_by_size::<GenericUnionFind<ByRank, PathHalving>>();
+        check_against_by_size::<GenericUnionFind<ByRank, PathSplitting>>();
+    }
+
+    #[test]
+    fn compression_shortens_paths() {
+        let mut uf = UnionFindBySizeWithCompression::from_nodes_and_starting_index(chain(5), 1);
+        assert_eq!(uf.find(5), 1);
+        assert_eq!(format!("{uf:?}"), "[-5, 1, 1, 1, 1]");
+
+        let mut uf =
+            GenericUnionFind::<BySize, PathHalving>::from_nodes_and_starting_index(chain(5), 1);
+        assert_eq!(uf.find(5), 1);
+        assert_eq!(format!("{uf:?}"), "[-5, 1, 1, 3, 3]");
+
+        let mut uf =
+            GenericUnionFind::<BySize, PathSplitting>::from_nodes_and_starting_index(chain(5), 1);
+        assert_eq!(uf.find(5), 1);
+        assert_eq!(format!("{uf:?}"), "[-5, 1, 1, 2, 3]");
+    }
+
+    fn check_queries<UF: UnionFind + Sync>() {
+        let mut uf = UF::new(9, 1);
+        for (a, b) in [(1, 2), (3, 4), (9, 8), (1, 7), (3, 5), (6, 3), (9, 3)] {
+            uf.union(a, b);
+        }
+        let uf = &uf;
+        std::thread::scope(|scope| {
+            scope.spawn(|| {
+                assert!(uf.same_set(1, 7));
+                assert!(uf.same_set(4, 9));
+                assert!(!uf.same_set(2, 5));
+            });
+            scope.spawn(|| {
+                assert_eq!(uf.num_sets(), 2);
+                assert_eq!(uf.set_size(2), 3);
+                assert_eq!(uf.set_size(8), 6);
+                assert_eq!(uf.members(7).collect::<Vec<_>>(), vec![1, 2, 7]);
+                assert_eq!(
+                    uf.members(uf.find_immutable(5)).collect::<Vec<_>>(),
+                    vec![3, 4, 5, 6, 8, 9]
+                );
+            });
+        });
+    }
+
+    #[test]
+    fn shared_queries() {
+        check_queries::<UnionFindBySize>();
+        check_queries::<UnionFindByHeight>();
+        check_queries::<UnionFindBySizeWithCompression>();
+        check_queries::<GenericUnionFind<ByRank, PathHalving>>();
+    }
+
+    #[test]
+    fn fallible_operations() {
+        let mut uf = UnionFindBySize::new(3, 1);
+        assert_eq!(uf.try_union(1, 3), Ok(()));
+        assert_eq!(uf.try_find(3), Ok(1));
+        let error = UnionFindError::OutOfBounds {
+            index: 4,
+            starting_index: 1,
+            len: 3,
+        };
+        assert_eq!(uf.try_find(4), Err(error));
+        assert_eq!(uf.try_union(2, 4), Err(error));
+        assert_eq!(
+            uf.try_find(0).unwrap_err().to_string(),
+            "index 0 is out of bounds, expected 1..4"
+        );
+    }
+
+    #[test]
+    fn validating_constructor() {
+        let nodes = vec![-5, 0, -12, 2, 2, 2, 0, 0, 7, 2, 2, 2, 2, 2, 13, 13, 15];
+        assert!(UnionFindBySize::try_from_nodes_and_starting_index(nodes.into(), 1).is_ok());
+        assert!(UnionFindBySize::try_from_nodes_and_starting_index(chain(1000), 0).is_ok());
+        // Long chains must validate in linear time.
+        assert!(UnionFindBySize::try_from_nodes_and_starting_index(chain(1_000_000), 0).is_ok());
+        assert_eq!(
+            UnionFindBySize::try_from_nodes_and_starting_index(vec![-1, 5, 0].into(), 1),
+            Err(UnionFindError::ParentOutOfRange { node: 2, parent: 6 })
+        );
+        assert_eq!(
+            UnionFindBySize::try_from_nodes_and_starting_index(vec![-1, 2, 3, 1].into(), 0),
+            Err(UnionFindError::Cycle { node: 1 })
+        );
+        assert_eq!(
+            UnionFindBySize::try_from_nodes_and_starting_index(vec![1, 1].into(), 0),
+            Err(UnionFindError::Cycle { node: 1 })
+        );
+        assert_eq!(
+            UnionFindBySize::try_from_nodes_and_starting_index(vec![-3, 0, -1].into(), 1),
+            Err(UnionFindError::SizeMismatch {
+                root: 1,
+                recorded: 3,
+                actual: 2
+            })
+        );
+        assert!(
+            UnionFindByHeight::try_from_nodes_and_starting_index(vec![-2, 0, -1].into(), 0).is_ok()
+        );
+        assert_eq!(
+            UnionFindByHeight::try_from_nodes_and_starting_index(chain(3), 0),
+            Err(UnionFindError::RankMismatch {
+                root: 0,
+                rank: 2,
+                height: 2,
+                size: 3
+            })
+        );
+        assert_eq!(
+            UnionFindBySize::try_from_nodes_and_starting_index(vec![isize::MIN].into(), 0),
+            Err(UnionFindError::SizeMismatch {
+                root: 0,
+                recorded: isize::MIN.unsigned_abs(),
+                actual: 1
+            })
+        );
+        assert_eq!(
+            UnionFindBySize::try_from_nodes_and_starting_index(vec![-1, isize::MAX].into(), 1),
+            Err(UnionFindError::ParentOutOfRange {
+                node: 2,
+                parent: isize::MAX
+            })
+        );
+        assert_eq!(
+            UnionFindBySize::try_from_nodes_and_starting_index(vec![-1, -1].into(), usize::MAX),
+            Err(UnionFindError::IndexOverflow {
+                starting_index: usize::MAX,
+                len: 2
+            })
+        );
+        assert!(UnionFindBySize::try_from_nodes_and_starting_index(
+            vec![-1].into(),
+            isize::MAX as usize - 1
+        )
+        .is_ok());
+        assert_eq!(
+            UnionFindByHeight::try_from_nodes_and_starting_index(vec![isize::MIN].into(), 0),
+            Err(UnionFindError::RankMismatch {
+                root: 0,
+                rank: isize::MAX as usize,
+                height: 0,
+                size: 1
+            })
+        );
+    }
+
+    #[test]
+    fn growing() {
+        let mut uf = UnionFindByHeight::new(2, 1);
+        uf.reserve(10);
+        assert_eq!(uf.make_set(), 3);
+        assert_eq!(uf.make_set(), 4);
+        uf.union(1, 4);
+        assert_eq!(uf.len(), 4);
+        assert_eq!(uf.num_sets(), 3);
+        assert!(uf.same_set(4, 1));
+        assert_eq!(format!("{uf:?}"), "[-2, -1, -1, 1]");
+    }
+
+    #[test]
+    fn keyed() {
+        let mut uf = KeyedUnionFind::<(i32, i32)>::new();
+        uf.union((0, 0), (0, 1));
+        uf.union((5, 5), (5, 6));
+        uf.union((0, 1), (1, 1));
+        assert_eq!(uf.len(), 5);
+        assert_eq!(uf.union_find().num_sets(), 2);
+        assert!(uf.same_set(&(0, 0), &(1, 1)));
+        assert!(!uf.same_set(&(0, 0), &(5, 6)));
+        assert!(!uf.same_set(&(0, 0), &(9, 9)));
+        assert_eq!(uf.find(&(5, 6)), Some(&(5, 5)));
+        assert_eq!(uf.find(&(9, 9)), None);
+
+        let mut uf = KeyedUnionFind::<String, UnionFindBySize>::with_capacity(3);
+        assert_eq!(uf.insert("a".to_owned()), 0);
+        uf.union("b".to_owned(), "c".to_owned());
+        assert_eq!(uf.insert("a".to_owned()), 0);
+        assert_eq!(uf.index_of("c"), Some(2));
+        assert_eq!(uf.key(1), "b");
+        assert!(uf.same_set("b", "c"));
+    }
+
+    #[test]
+    fn weighted_diffs() {
+        let mut uf = WeightedUnionFind::new(6, 1);
+        assert_eq!(uf.union_with_diff(1, 2, 3), Ok(()));
+        assert_eq!(uf.union_with_diff(3, 4, -2), Ok(()));
+        assert_eq!(uf.union_with_diff(2, 4, 10), Ok(()));
+        assert_eq!(uf.union_with_diff(5, 4, 1), Ok(()));
+        assert_eq!(uf.diff(1, 2), Some(3));
+        assert_eq!(uf.diff(2, 1), Some(-3));
+        assert_eq!(uf.diff(1, 3), Some(15));
+        assert_eq!(uf.diff(5, 3), Some(3));
+        assert_eq!(uf.diff(1, 6), None);
+        assert_eq!(uf.diff(6, 6), Some(0));
+        assert_eq!(uf.set_size(3), 5);
+        assert_eq!(uf.roots().count(), 2);
+
+        assert_eq!(uf.union_with_diff(1, 3, 15), Ok(()));
+        assert_eq!(
+            uf.union_with_diff(3, 1, 15),
+            Err(UnionFindError::InconsistentDiff {
+                a: 3,
+                b: 1,
+                requested: 15,
+                existing: -15
+            })
+        );
+        assert!(matches!(
+            uf.union_with_diff(1, 7, 0),
+            Err(UnionFindError::OutOfBounds { index: 7, .. })
+        ));
+    }
+
+    #[test]
+    fn components() {
+        let mut uf = UnionFindBySizeWithCompression::new(9, 1);
+        for (a, b) in [(9, 8), (3, 4), (1, 7), (3, 5), (6, 3)] {
+            uf.union(a, b);
+        }
+        assert_eq!(uf.component_labels(), vec![0, 1, 2, 2, 2, 2, 0, 3, 3]);
+        assert_eq!(
+            uf.components(),
+            vec![vec![1, 7], vec![2], vec![3, 4, 5, 6], vec![8, 9]]
+        );
+        assert_eq!(
+            UnionFindBySize::new(0, 5).components(),
+            Vec::<Vec<usize>>::new()
+        );
+
+        let mut uf = KeyedUnionFind::<&str>::new();
+        uf.union("x", "y");
+        uf.insert("z");
+        uf.union("w", "y");
+        assert_eq!(uf.components(), vec![vec![&"x", &"y", &"w"], vec![&"z"]]);
+    }
+
+    #[test]
+    fn long_chain_does_not_overflow() {
+        let n = 1_000_000;
+        let mut uf = UnionFindBySizeWithCompression::from_nodes_and_starting_index(chain(n), 0);
+        assert_eq!(uf.find(n - 1), 0);
+        assert_eq!(uf.find(n / 2), 0);
+    }
 }
@@ -0,0 +1,65 @@
+use bench_util::{format_duration, XorShift};
+use std::time::Instant;
+use union_find::{
+    ByRank, BySize, Compression, FullCompression, GenericUnionFind, Linking, NoCompression,
+    PathHalving, PathSplitting, UnionFind,
+};
+
+mod bench_util;
+mod union_find;
+
+const ELEMENTS: usize = 1_000_000;
+const OPERATIONS: usize = 2_000_000;
+
+// Seeded the same way every run so every strategy sees the same operations.
+fn random_pairs(n: usize, count: usize) -> Vec<(usize, usize)> {
+    let mut rng = XorShift::default();
+    let mut next = || rng.next_u64() as usize % n;
+    (0..count).map(|_| (next(), next())).collect()
+}
+
+// Worst case for the uncompressed strategies: one long path.
+fn chain(n: usize) -> Box<[isize]> {
+    (0..n as isize)
+        .map(|i| if i == 0 { -(n as isize) } else { i - 1 })
+        .collect()
+}
+
+fn bench<L: Linking, C: Compression>(name: &str, pairs: &[(usize, usize)]) {
+    let start = Instant::now();
+    let mut uf = GenericUnionFind::<L, C>::new(ELEMENTS, 0);
+    for (i, (a, b)) in pairs.iter().copied().enumerate() {
+        if i % 2 == 0 {
+            uf.union(a, b);
+        } else {
+            uf.find(a);
+        }
+    }
+    let random = start.elapsed();
+
+    let start = Instant::now();
+    let mut uf = GenericUnionFind::<L, C>::from_nodes_and_starting_index(chain(ELEMENTS), 0);
+    for x in (0..ELEMENTS).rev().step_by(ELEMENTS / 1000) {
+        uf.find(x);
+    }
+    let chain = start.elapsed();
+
+    println!(
+        "{name:<32} random: {:>10} chain: {:>10}",
+        format_duration(random),
+        format_duration(chain)
+    );
+}
+
+fn main() {
+    let pairs = random_pairs(ELEMENTS, OPERATIONS);
+    println!("{ELEMENTS} elements, {OPERATIONS} operations");
+    bench::<BySize, NoCompression>("BySize, NoCompression", &pairs);
+    bench::<BySize, FullCompression>("BySize, FullCompression", &pairs);
+    bench::<BySize, PathHalving>("BySize, PathHalving", &pairs);
+    bench::<BySize, PathSplitting>("BySize, PathSplitting", &pairs);
+    bench::<ByRank, NoCompression>("ByRank, NoCompression", &pairs);
+    bench::<ByRank, FullCompression>("ByRank, FullCompression", &pairs);
+    bench::<ByRank, PathHalving>("ByRank, PathHalving", &pairs);
+    bench::<ByRank, PathSplitting>("ByRank, PathSplitting", &pairs);
+}
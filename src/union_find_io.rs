@@ -0,0 +1,394 @@
+use std::error::Error;
+use std::fmt::{Display, Formatter, Result as FmtResult};
+use std::io::{self, BufRead, BufReader, Read, Write};
+
+use crate::union_find::{check_index_range, UnionFind, UnionFindError};
+
+const MAGIC: &[u8; 4] = b"UNFD";
+const TEXT_HEADER: &str = "union-find";
+const VERSION: u8 = 1;
+
+#[derive(Debug)]
+pub(crate) enum LoadError {
+    Io(io::Error),
+    BadMagic,
+    UnsupportedVersion(u64),
+    Malformed { line: usize, message: String },
+    MalformedBinary { offset: u64, message: String },
+    Invalid(UnionFindError),
+}
+
+impl Display for LoadError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> FmtResult {
+        match self {
+            LoadError::Io(error) => write!(f, "{error}"),
+            LoadError::BadMagic => write!(f, "not a union-find checkpoint"),
+            LoadError::UnsupportedVersion(version) => {
+                write!(f, "unsupported checkpoint version {version}")
+            }
+            LoadError::Malformed { line, message } => write!(f, "line {line}: {message}"),
+            LoadError::MalformedBinary { offset, message } => {
+                write!(f, "byte {offset}: {message}")
+            }
+            LoadError::Invalid(error) => write!(f, "invalid union-find: {error}"),
+        }
+    }
+}
+
+impl Error for LoadError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            LoadError::Io(error) => Some(error),
+            LoadError::Invalid(error) => Some(error),
+            _ => None,
+        }
+    }
+}
+
+impl From<io::Error> for LoadError {
+    fn from(value: io::Error) -> Self {
+        LoadError::Io(value)
+    }
+}
+
+impl From<UnionFindError> for LoadError {
+    fn from(value: UnionFindError) -> Self {
+        LoadError::Invalid(value)
+    }
+}
+
+// LEB128, with signed values zigzag encoded first so that the small negative
+// sizes stored at roots stay small.
+fn write_varint<W: Write>(writer: &mut W, mut value: u64) -> io::Result<()> {
+    loop {
+        let byte = (value & 0x7f) as u8;
+        value >>= 7;
+        if value == 0 {
+            return writer.write_all(&[byte]);
+        }
+        writer.write_all(&[byte | 0x80])?;
+    }
+}
+
+// At most ten bytes, the last of which only has room for the top bit of a
+// u64. `offset` counts the bytes read so far, for error messages.
+fn read_varint<R: Read>(reader: &mut R, offset: &mut u64) -> Result<u64, LoadError> {
+    let start = *offset;
+    let mut value = 0u64;
+    for shift in (0..64).step_by(7) {
+        let mut byte = [0];
+        reader.read_exact(&mut byte)?;
+        *offset += 1;
+        if shift == 63 && byte[0] & 0x7e != 0 {
+            return Err(LoadError::MalformedBinary {
+                offset: start,
+                message: "varint does not fit in 64 bits".to_owned(),
+            });
+        }
+        value |= ((byte[0] & 0x7f) as u64) << shift;
+        if byte[0] & 0x80 == 0 {
+            return Ok(value);
+        }
+    }
+    Err(LoadError::MalformedBinary {
+        offset: start,
+        message: "varint is too long".to_owned(),
+    })
+}
+
+fn zigzag(value: isize) -> u64 {
+    ((value << 1) ^ (value >> (isize::BITS - 1))) as u64
+}
+
+fn unzigzag(value: u64) -> isize {
+    ((value >> 1) as isize) ^ -((value & 1) as isize)
+}
+
+// Layout: magic, version, starting index, element count and then every node,
+// all as varints.
+pub(crate) fn write_binary<UF: UnionFind, W: Write>(uf: &UF, mut writer: W) -> io::Result<()> {
+    writer.write_all(MAGIC)?;
+    write_varint(&mut writer, VERSION as u64)?;
+    write_varint(&mut writer, uf.starting_index() as u64)?;
+    write_varint(&mut writer, uf.len() as u64)?;
+    for node in uf.nodes().iter().copied() {
+        write_varint(&mut writer, zigzag(node))?;
+    }
+    writer.flush()
+}
+
+pub(crate) fn read_binary<UF: UnionFind, R: Read>(mut reader: R) -> Result<UF, LoadError> {
+    let mut magic = [0; 4];
+    reader.read_exact(&mut magic)?;
+    if &magic != MAGIC {
+        return Err(LoadError::BadMagic);
+    }
+    let mut offset = MAGIC.len() as u64;
+    let version = read_varint(&mut reader, &mut offset)?;
+    if version != VERSION as u64 {
+        return Err(LoadError::UnsupportedVersion(version));
+    }
+    let starting_index = read_varint(&mut reader, &mut offset)? as usize;
+    let len = read_varint(&mut reader, &mut offset)? as usize;
+    check_index_range(starting_index, len)?;
+    // The length comes from untrusted input, so do not let it drive the
+    // allocation up front.
+    let mut nodes = Vec::with_capacity(len.min(1 << 16));
+    for _ in 0..len {
+        nodes.push(unzigzag(read_varint(&mut reader, &mut offset)?));
+    }
+    Ok(UF::try_from_nodes_and_starting_index(
+        nodes.into_boxed_slice(),
+        starting_index,
+    )?)
+}
+
+// Parents are written the same way as the `Debug` output, offset by the
+// starting index, so the file can be read in terms of the caller's elements.
+pub(crate) fn write_text<UF: UnionFind, W: Write>(uf: &UF, mut writer: W) -> io::Result<()> {
+    let starting_index = uf.starting_index() as isize;
+    writeln!(writer, "{TEXT_HEADER} {VERSION}")?;
+    writeln!(writer, "starting-index {starting_index}")?;
+    let nodes = uf
+        .nodes()
+        .iter()
+        .map(|&v| if v < 0 { v } else { v + starting_index }.to_string())
+        .collect::<Vec<_>>();
+    writeln!(writer, "{}", nodes.join(" "))?;
+    writer.flush()
+}
+
+pub(crate) fn read_text<UF: UnionFind, R: Read>(reader: R) -> Result<UF, LoadError> {
+    let mut lines = BufReader::new(reader).lines();
+    let mut line_number = 0;
+    let mut next_line = |expected: &str| -> Result<String, LoadError> {
+        line_number += 1;
+        lines
+            .next()
+            .transpose()?
+            .ok_or_else(|| LoadError::Malformed {
+                line: line_number,
+                message: format!("unexpected end of input, expected {expected}"),
+            })
+    };
+
+    let header = next_line("a header")?;
+    let version = match header.split_once(' ') {
+        Some((TEXT_HEADER, version)) => version,
+        _ => return Err(LoadError::BadMagic),
+    };
+    let version = version.trim().parse().map_err(|_| LoadError::Malformed {
+        line: 1,
+        message: format!("invalid version {version:?}"),
+    })?;
+    if version != VERSION as u64 {
+        return Err(LoadError::UnsupportedVersion(version));
+    }
+
+    let line = next_line("the starting index")?;
+    let starting_index = line
+        .strip_prefix("starting-index ")
+        .and_then(|value| value.trim().parse::<usize>().ok())
+        .ok_or_else(|| LoadError::Malformed {
+            line: 2,
+            message: format!("expected `starting-index <n>`, found {line:?}"),
+        })?;
+
+    let line = next_line("the nodes")?;
+    let values = line.split_whitespace().collect::<Vec<_>>();
+    // Every element, and so every offset below, fits in an isize after this.
+    check_index_range(starting_index, values.len())?;
+    let nodes = values
+        .into_iter()
+        .enumerate()
+        .map(|(index, value)| {
+            let value = value.parse::<isize>().map_err(|_| LoadError::Malformed {
+                line: 3,
+                message: format!(
+                    "node {} has invalid value {value:?}",
+                    index + starting_index
+                ),
+            })?;
+            if value < 0 {
+                Ok(value)
+            } else if value >= starting_index as isize {
+                Ok(value - starting_index as isize)
+            } else {
+                Err(LoadError::Invalid(UnionFindError::ParentOutOfRange {
+                    node: index + starting_index,
+                    parent: value,
+                }))
+            }
+        })
+        .collect::<Result<Vec<_>, _>>()?;
+    Ok(UF::try_from_nodes_and_starting_index(
+        nodes.into_boxed_slice(),
+        starting_index,
+    )?)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::persistent_union_find::RollbackUnionFind;
+    use crate::union_find::{UnionFindByHeight, UnionFindBySize, UnionFindBySizeWithCompression};
+
+    fn round_trip<UF: UnionFind>() {
+        let mut uf = UF::new(9, 1);
+        for (a, b) in [(1, 2), (3, 4), (9, 8), (1, 7), (3, 5), (6, 3), (9, 3)] {
+            uf.union(a, b);
+        }
+        uf.find(9);
+
+        let mut binary = Vec::new();
+        write_binary(&uf, &mut binary).unwrap();
+        let loaded = read_binary::<UF, _>(binary.as_slice()).unwrap();
+        assert_eq!(loaded.nodes(), uf.nodes());
+        assert_eq!(loaded.starting_index(), 1);
+
+        let mut text = Vec::new();
+        write_text(&uf, &mut text).unwrap();
+        let text = String::from_utf8(text).unwrap();
+        assert!(text.starts_with("union-find 1\nstarting-index 1\n"));
+        let loaded = read_text::<UF, _>(text.as_bytes()).unwrap();
+        assert_eq!(loaded.nodes(), uf.nodes());
+        assert_eq!(loaded.starting_index(), 1);
+    }
+
+    #[test]
+    fn round_trips() {
+        round_trip::<UnionFindBySize>();
+        round_trip::<UnionFindByHeight>();
+        round_trip::<UnionFindBySizeWithCompression>();
+        round_trip::<RollbackUnionFind>();
+    }
+
+    #[test]
+    fn binary_is_compact() {
+        let uf = UnionFindBySize::new(1000, 0);
+        let mut binary = Vec::new();
+        write_binary(&uf, &mut binary).unwrap();
+        assert_eq!(binary.len(), 4 + 1 + 1 + 2 + 1000);
+    }
+
+    #[test]
+    fn rejects_bad_input() {
+        let text = "union-find 1\nstarting-index 1\n-3 1 1 -1\n";
+        assert!(read_text::<UnionFindBySize, _>(text.as_bytes()).is_ok());
+        assert!(matches!(
+            read_text::<UnionFindByHeight, _>(text.as_bytes()),
+            Err(LoadError::Invalid(UnionFindError::RankMismatch {
+                root: 1,
+                ..
+            }))
+        ));
+        assert!(matches!(
+            read_text::<UnionFindBySize, _>(
+                "union-find 1\nstarting-index 1\n-3 1 1 -2\n".as_bytes()
+            ),
+            Err(LoadError::Invalid(UnionFindError::SizeMismatch {
+                root: 4,
+                ..
+            }))
+        ));
+        assert!(matches!(
+            read_text::<UnionFindBySize, _>("union-find 1\nstarting-index 1\n-1 0\n".as_bytes()),
+            Err(LoadError::Invalid(UnionFindError::ParentOutOfRange {
+                node: 2,
+                parent: 0
+            }))
+        ));
+        assert!(matches!(
+            read_text::<UnionFindBySize, _>("union-find 2\n".as_bytes()),
+            Err(LoadError::UnsupportedVersion(2))
+        ));
+        assert!(matches!(
+            read_text::<UnionFindBySize, _>("union-find 1\nstarting-index 1\n-1 x\n".as_bytes()),
+            Err(LoadError::Malformed { line: 3, .. })
+        ));
+        assert!(matches!(
+            read_text::<UnionFindBySize, _>("union-find 1\n".as_bytes()),
+            Err(LoadError::Malformed { line: 2, .. })
+        ));
+        assert!(matches!(
+            read_binary::<UnionFindBySize, _>(&b"UNFX\x01"[..]),
+            Err(LoadError::BadMagic)
+        ));
+        let mut offset = 0;
+        assert_eq!(
+            read_varint(
+                &mut &b"\xff\xff\xff\xff\xff\xff\xff\xff\xff\x01"[..],
+                &mut offset
+            )
+            .unwrap(),
+            u64::MAX
+        );
+        assert_eq!(offset, 10);
+        for overflowing in [
+            &b"\xff\xff\xff\xff\xff\xff\xff\xff\xff\x02"[..],
+            b"\xff\xff\xff\xff\xff\xff\xff\xff\xff\x81\x00",
+            b"\x80\x80\x80\x80\x80\x80\x80\x80\x80\x80\x00",
+        ] {
+            assert!(matches!(
+                read_varint(&mut &overflowing[..], &mut 0),
+                Err(LoadError::MalformedBinary { offset: 0, .. })
+            ));
+        }
+        // The node count starts at byte 6.
+        let long_len = b"UNFD\x01\x00\x80\x80\x80\x80\x80\x80\x80\x80\x80\x80\x00";
+        let error = read_binary::<UnionFindBySize, _>(&long_len[..]).unwrap_err();
+        assert!(matches!(
+            error,
+            LoadError::MalformedBinary { offset: 6, .. }
+        ));
+        assert_eq!(error.to_string(), "byte 6: varint is too long");
+        // A single node that zigzag decodes to `isize::MIN`.
+        let min_root = b"UNFD\x01\x00\x01\xff\xff\xff\xff\xff\xff\xff\xff\xff\x01";
+        assert!(matches!(
+            read_binary::<UnionFindBySize, _>(&min_root[..]),
+            Err(LoadError::Invalid(UnionFindError::SizeMismatch {
+                root: 0,
+                ..
+            }))
+        ));
+        assert!(matches!(
+            read_binary::<UnionFindByHeight, _>(&min_root[..]),
+            Err(LoadError::Invalid(UnionFindError::RankMismatch {
+                root: 0,
+                ..
+            }))
+        ));
+        assert!(matches!(
+            read_binary::<UnionFindBySize, _>(&b"UNFD\x01\x00\x05\x01"[..]),
+            Err(LoadError::Io(_))
+        ));
+        assert!(matches!(
+            read_binary::<UnionFindBySize, _>(&b"UNFD\x01\x00\x02\x01\x02"[..]),
+            Err(LoadError::Invalid(UnionFindError::Cycle { node: 1 }))
+        ));
+    }
+
+    #[test]
+    fn rejects_overflowing_starting_index() {
+        let binary = b"UNFD\x01\xff\xff\xff\xff\xff\xff\xff\xff\xff\x01\x02\x01\x01";
+        assert!(matches!(
+            read_binary::<UnionFindBySize, _>(&binary[..]),
+            Err(LoadError::Invalid(UnionFindError::IndexOverflow {
+                starting_index: usize::MAX,
+                len: 2
+            }))
+        ));
+        for text in [
+            "union-find 1\nstarting-index 18446744073709551615\n-1 x\n",
+            "union-find 1\nstarting-index 9223372036854775807\n-1\n",
+        ] {
+            assert!(matches!(
+                read_text::<UnionFindBySize, _>(text.as_bytes()),
+                Err(LoadError::Invalid(UnionFindError::IndexOverflow { .. }))
+            ));
+        }
+        let text = "union-find 1\nstarting-index 9223372036854775806\n-1\n";
+        let loaded = read_text::<UnionFindBySize, _>(text.as_bytes()).unwrap();
+        assert_eq!(loaded.starting_index(), isize::MAX as usize - 1);
+    }
+}
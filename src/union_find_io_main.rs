@@ -0,0 +1,32 @@
+use union_find::{UnionFind, UnionFindBySizeWithCompression};
+use union_find_io::{read_binary, read_text, write_binary, write_text};
+
+mod persistent_union_find;
+mod union_find;
+mod union_find_io;
+
+fn main() {
+    let mut uf = UnionFindBySizeWithCompression::new(9, 1);
+    for (a, b) in [(1, 2), (3, 4), (9, 8), (1, 7), (3, 5), (6, 3), (9, 3)] {
+        uf.union(a, b);
+    }
+    println!("Original: {uf:?}");
+
+    let mut text = Vec::new();
+    write_text(&uf, &mut text).unwrap();
+    println!("Text checkpoint:\n{}", String::from_utf8_lossy(&text));
+    let restored: UnionFindBySizeWithCompression = read_text(text.as_slice()).unwrap();
+    println!("Restored from text: {restored:?}");
+
+    let mut binary = Vec::new();
+    write_binary(&uf, &mut binary).unwrap();
+    println!("Binary checkpoint: {binary:?}");
+    let restored: UnionFindBySizeWithCompression = read_binary(binary.as_slice()).unwrap();
+    println!("Restored from binary: {restored:?}");
+
+    let corrupted = "union-find 1\nstarting-index 1\n-3 1 4 -1\n";
+    match read_text::<UnionFindBySizeWithCompression, _>(corrupted.as_bytes()) {
+        Ok(uf) => println!("Unexpectedly loaded {uf:?}"),
+        Err(error) => println!("Rejected corrupted checkpoint: {error}"),
+    }
+}